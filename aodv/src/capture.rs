@@ -0,0 +1,180 @@
+use mesh_proto::Packet;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+// pcap file header fields, see https://wiki.wireshark.org/Development/LibpcapFileFormat
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION: (u16, u16) = (2, 4);
+const PCAP_SNAPLEN: u32 = 65_535;
+// LINKTYPE_USER0, reserved for private use
+const LINKTYPE_USER0: u32 = 147;
+// Destination written for broadcasts in the pcap pseudo header
+const BROADCAST: u16 = u16::MAX;
+
+#[derive(Serialize)]
+struct Record<'a> {
+    // Seconds since the Unix epoch, or since the start of a headless run
+    time: f64,
+    source: usize,
+    // None for a broadcast
+    destination: Option<usize>,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    size: usize,
+    // How much of a packet too large for its trace was kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    captured: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    packet: Option<Packet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// Writes every packet sent between drones to PATH.jsonl, one decoded packet per line, and
+// to PATH.pcap for Wireshark. Each pcap frame is a 4 byte pseudo header, the source and
+// destination drone as big endian u16 with 0xffff for broadcasts, followed by the packet
+// exactly as it was sent. Packets a drone could only trace in part are written as far as
+// they go, with their full length as the pcap original length.
+pub struct Capture {
+    jsonl: BufWriter<File>,
+    pcap: BufWriter<File>,
+    packets: u64,
+}
+
+impl Capture {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let jsonl = BufWriter::new(File::create(path.with_extension("jsonl"))?);
+        let mut pcap = BufWriter::new(File::create(path.with_extension("pcap"))?);
+
+        pcap.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        pcap.write_all(&PCAP_VERSION.0.to_le_bytes())?;
+        pcap.write_all(&PCAP_VERSION.1.to_le_bytes())?;
+        // Timestamps are UTC and have no accuracy information
+        pcap.write_all(&0i32.to_le_bytes())?;
+        pcap.write_all(&0u32.to_le_bytes())?;
+        pcap.write_all(&PCAP_SNAPLEN.to_le_bytes())?;
+        pcap.write_all(&LINKTYPE_USER0.to_le_bytes())?;
+        pcap.flush()?;
+
+        Ok(Capture {
+            jsonl,
+            pcap,
+            packets: 0,
+        })
+    }
+
+    // Both files are flushed after every packet so a run that is killed keeps its trace.
+    // Length is the size of the packet as it was sent, data may be less of it.
+    pub fn record(
+        &mut self,
+        time: Duration,
+        source: usize,
+        destination: Option<usize>,
+        data: &[u8],
+        length: usize,
+    ) -> io::Result<()> {
+        let truncated = data.len() < length;
+        let decoded = if truncated {
+            Err(format!(
+                "only {} of {} bytes were captured",
+                data.len(),
+                length
+            ))
+        } else {
+            Packet::decode(data).map_err(|e| e.to_string())
+        };
+        let record = Record {
+            time: time.as_secs_f64(),
+            source,
+            destination,
+            kind: kind(data, length),
+            size: length,
+            captured: truncated.then_some(data.len()),
+            error: decoded.as_ref().err().cloned(),
+            packet: decoded.ok(),
+        };
+        serde_json::to_writer(&mut self.jsonl, &record)?;
+        self.jsonl.write_all(b"\n")?;
+        self.jsonl.flush()?;
+
+        let captured_length = (data.len() + 4) as u32;
+        let original_length = (length.max(data.len()) + 4) as u32;
+        self.pcap
+            .write_all(&(time.as_secs() as u32).to_le_bytes())?;
+        self.pcap.write_all(&time.subsec_micros().to_le_bytes())?;
+        self.pcap.write_all(&captured_length.to_le_bytes())?;
+        self.pcap.write_all(&original_length.to_le_bytes())?;
+        self.pcap.write_all(&(source as u16).to_be_bytes())?;
+        self.pcap
+            .write_all(&destination.map_or(BROADCAST, |id| id as u16).to_be_bytes())?;
+        self.pcap.write_all(data)?;
+        self.pcap.flush()?;
+
+        self.packets += 1;
+        Ok(())
+    }
+
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+}
+
+// The packet's name, if enough of it was captured to decode it
+pub fn kind(data: &[u8], length: usize) -> &'static str {
+    if data.len() < length {
+        return "TRUNCATED";
+    }
+    Packet::decode(data).map_or("MALFORMED", |packet| packet.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn truncated_trace_keeps_its_original_length() {
+        let path = std::env::temp_dir().join(format!("aodv-capture-{}", std::process::id()));
+        let sent = Packet::Send {
+            destination: 2,
+            payload: "x".repeat(1000),
+        }
+        .encode()
+        .unwrap();
+        let Packet::Trace { length, data, .. } = Packet::trace(1, Some(2), &sent) else {
+            unreachable!();
+        };
+        assert!(data.len() < length);
+
+        let mut capture = Capture::create(&path).unwrap();
+        capture
+            .record(Duration::from_millis(1500), 1, Some(2), &data, length)
+            .unwrap();
+        drop(capture);
+
+        let jsonl = fs::read_to_string(path.with_extension("jsonl")).unwrap();
+        let record: serde_json::Value = serde_json::from_str(jsonl.trim()).unwrap();
+        assert_eq!(record["type"], "TRUNCATED");
+        assert_eq!(record["size"], sent.len());
+        assert_eq!(record["captured"], data.len());
+
+        // Past the 24 byte file header: seconds, microseconds, captured and original length
+        let pcap = fs::read(path.with_extension("pcap")).unwrap();
+        let field =
+            |offset: usize| u32::from_le_bytes(pcap[offset..offset + 4].try_into().unwrap());
+        assert_eq!(field(24), 1);
+        assert_eq!(field(28), 500_000);
+        assert_eq!(field(32) as usize, data.len() + 4);
+        assert_eq!(field(36) as usize, sent.len() + 4);
+        assert_eq!(pcap.len(), 24 + 16 + 4 + data.len());
+
+        fs::remove_file(path.with_extension("jsonl")).unwrap();
+        fs::remove_file(path.with_extension("pcap")).unwrap();
+    }
+}
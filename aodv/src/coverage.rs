@@ -0,0 +1,135 @@
+use mesh_proto::geometry::{area, bounds, contains};
+use mesh_proto::Coordinate;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Keeps the grid small enough to update on every position report
+const MAX_CELLS: f32 = 250_000.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverageReport {
+    pub covered: usize,
+    pub total: usize,
+    pub percent: f32,
+}
+
+// Which parts of a search area the drones have flown over. The area is split into square
+// cells of half a spacing, and a cell is covered once its middle has been within half a
+// spacing of a drone's track. Cells in obstacles have nothing to search and do not count.
+pub struct Coverage {
+    area: Vec<Coordinate>,
+    origin: Coordinate,
+    cell: f32,
+    columns: usize,
+    // None for cells outside the area or in an obstacle
+    cells: Vec<Option<bool>>,
+    radius: f32,
+    // Where each drone was last seen, so the track between two reports is covered too
+    last_seen: BTreeMap<usize, Coordinate>,
+}
+
+impl Coverage {
+    pub fn new(polygon: &[Coordinate], obstacles: &[Vec<Coordinate>], spacing: f32) -> Self {
+        let (min, max) = bounds(polygon);
+        let cell = (spacing / 2.0).max((area(polygon) / MAX_CELLS).sqrt());
+        let columns = (((max.x - min.x) / cell).ceil() as usize).max(1);
+        let rows = (((max.y - min.y) / cell).ceil() as usize).max(1);
+
+        let mut cells = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let middle = Coordinate {
+                    x: min.x + (column as f32 + 0.5) * cell,
+                    y: min.y + (row as f32 + 0.5) * cell,
+                };
+                let searched = contains(polygon, &middle)
+                    && !obstacles.iter().any(|obstacle| contains(obstacle, &middle));
+                cells.push(searched.then_some(false));
+            }
+        }
+        Coverage {
+            area: polygon.to_vec(),
+            origin: min,
+            cell,
+            columns,
+            cells,
+            radius: spacing / 2.0,
+            last_seen: BTreeMap::new(),
+        }
+    }
+
+    pub fn area(&self) -> &[Coordinate] {
+        &self.area
+    }
+
+    pub fn mark(&mut self, id: usize, position: &Coordinate) {
+        let from = self
+            .last_seen
+            .insert(id, position.clone())
+            .unwrap_or_else(|| position.clone());
+
+        let reach = self.radius + self.cell;
+        let first_column = self.column(from.x.min(position.x) - reach);
+        let last_column = self.column(from.x.max(position.x) + reach);
+        let first_row = self.row(from.y.min(position.y) - reach);
+        let last_row = self.row(from.y.max(position.y) + reach);
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                let index = row * self.columns + column;
+                let Some(Some(false)) = self.cells.get(index) else {
+                    continue;
+                };
+                let middle = Coordinate {
+                    x: self.origin.x + (column as f32 + 0.5) * self.cell,
+                    y: self.origin.y + (row as f32 + 0.5) * self.cell,
+                };
+                if distance_to_segment(&middle, &from, position) <= self.radius {
+                    self.cells[index] = Some(true);
+                }
+            }
+        }
+    }
+
+    pub fn report(&self) -> CoverageReport {
+        let total = self.cells.iter().filter(|cell| cell.is_some()).count();
+        let covered = self
+            .cells
+            .iter()
+            .filter(|cell| **cell == Some(true))
+            .count();
+        CoverageReport {
+            covered,
+            total,
+            percent: if total == 0 {
+                0.0
+            } else {
+                100.0 * covered as f32 / total as f32
+            },
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.cells.len() / self.columns
+    }
+
+    // The cell a coordinate falls in, clamped to the grid
+    fn column(&self, x: f32) -> usize {
+        (((x - self.origin.x) / self.cell).max(0.0) as usize).min(self.columns - 1)
+    }
+
+    fn row(&self, y: f32) -> usize {
+        (((y - self.origin.y) / self.cell).max(0.0) as usize).min(self.rows() - 1)
+    }
+}
+
+fn distance_to_segment(point: &Coordinate, a: &Coordinate, b: &Coordinate) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((point.x - a.x) * dx + (point.y - a.y) * dy) / length).clamp(0.0, 1.0)
+    };
+    let (x, y) = (a.x + t * dx - point.x, a.y + t * dy - point.y);
+    (x * x + y * y).sqrt()
+}
@@ -0,0 +1,598 @@
+use crate::capture::Capture;
+use crate::persons::{DetectionReport, MissingPersons};
+use crate::radio::{RadioConfig, RadioModel, RadioStats};
+use crate::recording::{Input, Outcome, Recording};
+use crate::scenario::{DroneSpec, Scenario};
+use drone::{Drone, DroneConfig, Endpoint, SimClock, Transport};
+use mesh_proto::{Coordinate, DroneData, Mission, Packet};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io,
+    rc::Rc,
+    time::Duration,
+};
+
+// Packets can trigger more packets, so delivery within a tick is bounded
+const MAX_ROUNDS_PER_TICK: usize = 1000;
+
+struct Envelope {
+    from: usize,
+    // None is a broadcast to every other drone
+    to: Option<Endpoint>,
+    data: Vec<u8>,
+}
+
+// Packets in flight between the drones running in the engine
+#[derive(Default)]
+struct Medium {
+    outbox: VecDeque<Envelope>,
+    inboxes: BTreeMap<usize, VecDeque<Vec<u8>>>,
+}
+
+impl Medium {
+    fn is_idle(&self) -> bool {
+        self.outbox.is_empty() && self.inboxes.values().all(VecDeque::is_empty)
+    }
+}
+
+struct EngineTransport {
+    id: usize,
+    medium: Rc<RefCell<Medium>>,
+}
+
+impl Transport for EngineTransport {
+    fn send(&self, data: &[u8], to: Endpoint) -> io::Result<()> {
+        self.medium.borrow_mut().outbox.push_back(Envelope {
+            from: self.id,
+            to: Some(to),
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn broadcast(&self, data: &[u8]) -> io::Result<()> {
+        self.medium.borrow_mut().outbox.push_back(Envelope {
+            from: self.id,
+            to: None,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+
+    // Simulated time does not pass while waiting, so this never blocks
+    fn recv(&mut self, buffer: &mut [u8], _timeout: Duration) -> io::Result<Option<usize>> {
+        let mut medium = self.medium.borrow_mut();
+        let Some(data) = medium
+            .inboxes
+            .get_mut(&self.id)
+            .and_then(VecDeque::pop_front)
+        else {
+            return Ok(None);
+        };
+        let size = data.len().min(buffer.len());
+        buffer[..size].copy_from_slice(&data[..size]);
+        Ok(Some(size))
+    }
+}
+
+// Runs the drones as in-process state machines on a simulated clock. Traffic between
+// drones goes through the radio model, and within a tick the clock jumps from one packet
+// arrival to the next, so the same input and seed always give the same run.
+pub struct Engine {
+    config: DroneConfig,
+    clock: SimClock,
+    medium: Rc<RefCell<Medium>>,
+    radio: RadioModel,
+    drones: BTreeMap<usize, Drone>,
+    specs: BTreeMap<usize, DroneSpec>,
+    positions: BTreeMap<usize, DroneData>,
+    edges: Vec<(usize, DroneData)>,
+    // Drones flying a mission, they get mission steps instead of moves
+    missions: BTreeSet<usize>,
+    persons: MissingPersons,
+    detections: Vec<DetectionReport>,
+    // Records drone traffic with simulated timestamps
+    capture: Option<Capture>,
+    tick: Duration,
+    ticks: u64,
+    finished: bool,
+}
+
+impl Engine {
+    pub fn new(scenario: &Scenario, config: DroneConfig, radio: RadioConfig) -> Self {
+        // The scenario decides whether there is a base station to report to
+        let config = DroneConfig {
+            base_station: scenario.base_station_id(),
+            ..config
+        };
+        let clock = SimClock::new();
+        let medium = Rc::new(RefCell::new(Medium::default()));
+        let mut engine = Engine {
+            persons: MissingPersons::new(scenario, &config),
+            config,
+            clock,
+            medium,
+            radio: RadioModel::new(radio),
+            drones: BTreeMap::new(),
+            specs: BTreeMap::new(),
+            positions: BTreeMap::new(),
+            edges: scenario.edges(),
+            missions: BTreeSet::new(),
+            detections: Vec::new(),
+            capture: None,
+            tick: Duration::from_millis(scenario.simulation.tick_ms),
+            ticks: 0,
+            finished: false,
+        };
+
+        for spec in &scenario.nodes() {
+            engine.start_drone(spec);
+        }
+        engine
+    }
+
+    fn start_drone(&mut self, spec: &DroneSpec) {
+        let transport = EngineTransport {
+            id: spec.id,
+            medium: self.medium.clone(),
+        };
+        let drone = Drone::with_clock(
+            spec.id,
+            spec.position.clone(),
+            transport,
+            false,
+            // Telemetry is for the live view of the networked simulator
+            DroneConfig {
+                telemetry: false,
+                ..spec.config(&self.config)
+            },
+            Rc::new(self.clock.clone()),
+        );
+        self.medium
+            .borrow_mut()
+            .inboxes
+            .insert(spec.id, VecDeque::new());
+        self.drones.insert(spec.id, drone);
+        self.specs.insert(spec.id, spec.clone());
+        self.positions.insert(spec.id, spec.data());
+    }
+
+    // Adds a drone linked to the drones within radio range of where they are now, like
+    // adding a drone to the networked simulator
+    pub fn add_drone(&mut self, spec: &DroneSpec) {
+        if self.specs.contains_key(&spec.id) {
+            println!("Drone {} already exists", spec.id);
+            return;
+        }
+        for (id, other) in &self.specs {
+            let Some(current) = self.positions.get(id) else {
+                continue;
+            };
+            let moved = DroneSpec {
+                position: Coordinate {
+                    x: current.x,
+                    y: current.y,
+                },
+                ..other.clone()
+            };
+            if spec.in_range_of(&moved) {
+                self.edges.push((spec.id, current.clone()));
+                self.edges.push((*id, spec.data()));
+            }
+        }
+        self.start_drone(spec);
+    }
+
+    // Tells the drone to shut down, it leaves during the next tick
+    pub fn remove_drone(&mut self, id: usize) {
+        self.send_to_drone(&Packet::Shutdown, id);
+        self.forget_drone(id);
+    }
+
+    // Drops the drone without letting it send anything, like a crash
+    pub fn kill_drone(&mut self, id: usize) {
+        self.drones.remove(&id);
+        self.medium.borrow_mut().inboxes.remove(&id);
+        self.forget_drone(id);
+    }
+
+    fn forget_drone(&mut self, id: usize) {
+        self.specs.remove(&id);
+        self.positions.remove(&id);
+        self.missions.remove(&id);
+        self.edges
+            .retain(|(drone, neighbor)| *drone != id && neighbor.id != id);
+    }
+
+    pub fn move_drone(&mut self, id: usize, target: &Coordinate) {
+        let message = Packet::Move {
+            target: target.clone(),
+        };
+        self.send_to_drone(&message, id);
+        // Moving a drone takes it off its mission
+        self.missions.remove(&id);
+        self.tick();
+    }
+
+    pub fn start_mission(&mut self, id: usize, mission: &Mission) {
+        if self.drones.contains_key(&id) {
+            self.send_to_drone(&Packet::Mission(mission.clone()), id);
+            self.missions.insert(id);
+        }
+    }
+
+    pub fn cancel_mission(&mut self) {
+        for id in std::mem::take(&mut self.missions) {
+            self.send_to_drone(&Packet::CancelMission, id);
+        }
+    }
+
+    // Applies one recorded input. Steps and moves take a tick, the rest happens right away.
+    // Returns false for a reset, which needs a new engine.
+    pub fn apply(&mut self, input: &Input) -> bool {
+        match input {
+            Input::Step { target } => self.step(target.as_ref()),
+            Input::MoveDrone { id, target } => self.move_drone(*id, target),
+            Input::AddDrone { drone } => self.add_drone(drone),
+            Input::RemoveDrone { id } => self.remove_drone(*id),
+            Input::KillDrone { id } => self.kill_drone(*id),
+            // Targets only matter to the steps, which carry their own
+            Input::SetTargets { .. } => self.finished = false,
+            Input::StartMission { id, mission } => self.start_mission(*id, mission),
+            Input::CancelMission => self.cancel_mission(),
+            Input::Reset => return false,
+        }
+        true
+    }
+
+    // Runs the inputs of a recording on a new engine, starting over at every reset
+    pub fn replay(recording: &Recording) -> Self {
+        let new_engine = || {
+            Engine::new(
+                &recording.scenario,
+                recording.drone_config.clone(),
+                recording.radio.clone(),
+            )
+        };
+
+        let mut engine = new_engine();
+        for input in &recording.inputs {
+            if !engine.apply(input) {
+                engine = new_engine();
+            }
+        }
+        engine
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        Outcome {
+            ticks: self.ticks,
+            finished: self.finished,
+            digest: format!("{:016x}", self.digest()),
+        }
+    }
+
+    // FNV-1a hash of the state a run ends in. It only depends on the run, not on the
+    // platform or the Rust version, so digests from different builds can be compared.
+    fn digest(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut add = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+        add(self.ticks);
+        add(self.finished as u64);
+        add(self.clock.elapsed().as_nanos() as u64);
+        for position in self.positions.values() {
+            add(position.id as u64);
+            add(position.x.to_bits() as u64);
+            add(position.y.to_bits() as u64);
+        }
+        for detection in &self.detections {
+            add(detection.id as u64);
+            add(detection.person.map_or(u64::MAX, |person| person as u64));
+            add(detection.confidence.to_bits() as u64);
+        }
+        let stats = self.radio.stats();
+        add(stats.sent);
+        add(stats.delivered);
+        add(stats.lost);
+        hash
+    }
+
+    // Moves every drone one step along its mission or towards the target, like a step
+    // from the frontend
+    pub fn step(&mut self, target: Option<&Coordinate>) {
+        let ids: Vec<usize> = self.drones.keys().copied().collect();
+        for id in ids {
+            let message = if self.missions.contains(&id) {
+                Packet::MissionStep
+            } else if let Some(target) = target {
+                Packet::Move {
+                    target: target.clone(),
+                }
+            } else {
+                continue;
+            };
+            self.send_to_drone(&message, id);
+        }
+        self.tick();
+    }
+
+    pub fn tick(&mut self) {
+        let end = self.clock.elapsed() + self.tick;
+        self.ticks += 1;
+
+        loop {
+            let next = match self.radio.next_arrival() {
+                Some(arrival) if arrival < end => arrival,
+                _ => end,
+            };
+            self.clock
+                .advance(next.saturating_sub(self.clock.elapsed()));
+            self.run_rounds();
+            if next == end {
+                return;
+            }
+        }
+    }
+
+    // Lets the drones handle packets until nothing more arrives at the current time
+    fn run_rounds(&mut self) {
+        for _ in 0..MAX_ROUNDS_PER_TICK {
+            self.receive_arrived();
+            let mut gone_home = Vec::new();
+            for (id, drone) in self.drones.iter_mut() {
+                if !drone.step(Duration::ZERO) {
+                    gone_home.push(*id);
+                }
+            }
+            self.deliver();
+
+            for id in gone_home {
+                self.drones.remove(&id);
+                self.medium.borrow_mut().inboxes.remove(&id);
+            }
+            let now = self.clock.elapsed();
+            if self.medium.borrow().is_idle()
+                && self
+                    .radio
+                    .next_arrival()
+                    .is_none_or(|arrival| arrival > now)
+            {
+                return;
+            }
+        }
+        println!("Network still busy after {} rounds", MAX_ROUNDS_PER_TICK);
+    }
+
+    pub fn positions(&self) -> Vec<DroneData> {
+        self.positions.values().cloned().collect()
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    pub fn detections(&self) -> &[DetectionReport] {
+        &self.detections
+    }
+
+    // How many of the missing persons some drone has reported
+    pub fn persons_found(&self) -> (usize, usize) {
+        let found: BTreeSet<usize> = self
+            .detections
+            .iter()
+            .filter_map(|detection| detection.person)
+            .collect();
+        (found.len(), self.persons.len())
+    }
+
+    pub fn radio_stats(&self) -> &RadioStats {
+        self.radio.stats()
+    }
+
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    pub fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref()
+    }
+
+    fn send_to_drone(&self, packet: &Packet, id: usize) {
+        let data = match packet.encode() {
+            Ok(data) => data,
+            Err(e) => {
+                println!("Could not send {} to drone {}: {}", packet.name(), id, e);
+                return;
+            }
+        };
+        if let Some(inbox) = self.medium.borrow_mut().inboxes.get_mut(&id) {
+            inbox.push_back(data);
+        }
+    }
+
+    // Same handshake as the simulator: acknowledge, then send the drone its neighbors
+    fn configure_drone(&self, id: usize) {
+        self.send_to_drone(&Packet::RegisterAck, id);
+        for (_, neighbor) in self.edges.iter().filter(|(drone, _)| *drone == id) {
+            let add_neighbor = Packet::AddNeighbor {
+                id: neighbor.id,
+                position: Coordinate {
+                    x: neighbor.x,
+                    y: neighbor.y,
+                },
+            };
+            self.send_to_drone(&add_neighbor, id);
+        }
+    }
+
+    // Reports go straight to the simulator, drone traffic is handed to the radio
+    fn deliver(&mut self) {
+        let outbox: Vec<Envelope> = self.medium.borrow_mut().outbox.drain(..).collect();
+        for envelope in outbox {
+            let receivers: Vec<DroneData> = match envelope.to {
+                Some(Endpoint::Simulator) => {
+                    self.receive_report(&envelope.data);
+                    continue;
+                }
+                Some(Endpoint::Drone(id)) => self.active_positions(|other| other == id),
+                None => self.active_positions(|other| other != envelope.from),
+            };
+            self.record(&envelope);
+            if let Some(sender) = self.positions.get(&envelope.from) {
+                self.radio
+                    .transmit(self.clock.elapsed(), sender, &receivers, &envelope.data);
+            }
+        }
+    }
+
+    fn record(&mut self, envelope: &Envelope) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+        let destination = match envelope.to {
+            Some(Endpoint::Drone(id)) => Some(id),
+            _ => None,
+        };
+        if let Err(e) = capture.record(
+            self.clock.elapsed(),
+            envelope.from,
+            destination,
+            &envelope.data,
+            envelope.data.len(),
+        ) {
+            println!("Could not write packet capture: {}", e);
+            self.capture = None;
+        }
+    }
+
+    fn receive_arrived(&mut self) {
+        let mut medium = self.medium.borrow_mut();
+        for (id, data) in self.radio.arrived(self.clock.elapsed()) {
+            // Drones that have gone home no longer listen
+            if let Some(inbox) = medium.inboxes.get_mut(&id) {
+                inbox.push_back(data);
+            }
+        }
+    }
+
+    fn active_positions(&self, include: impl Fn(usize) -> bool) -> Vec<DroneData> {
+        self.drones
+            .keys()
+            .filter(|id| include(**id))
+            .filter_map(|id| self.positions.get(id).cloned())
+            .collect()
+    }
+
+    fn receive_report(&mut self, data: &[u8]) {
+        match Packet::decode(data) {
+            Ok(Packet::Register { id, .. }) => self.configure_drone(id),
+            Ok(Packet::Finished { id }) => {
+                if self.config.verbose {
+                    println!("Target reached by drone {}", id);
+                }
+                self.finished = true;
+            }
+            Ok(Packet::ReportReceived(report)) => {
+                if self.config.verbose {
+                    println!(
+                        "Base station received a report from drone {}",
+                        report.finder
+                    );
+                }
+                self.finished = true;
+            }
+            Ok(Packet::Position(data)) => {
+                let position = Coordinate {
+                    x: data.x,
+                    y: data.y,
+                };
+                self.send_to_drone(&self.persons.frame(&position), data.id);
+                self.positions.insert(data.id, data);
+            }
+            Ok(Packet::Detection {
+                id,
+                position,
+                confidence,
+            }) => {
+                let report = self.persons.identify(id, position, confidence);
+                if self.config.verbose {
+                    println!("Drone {} reported a detection: {:?}", id, report);
+                }
+                self.detections.push(report);
+            }
+            Ok(Packet::MissionComplete { id }) => {
+                if self.config.verbose {
+                    println!("Drone {} has completed its mission", id);
+                }
+                self.missions.remove(&id);
+            }
+            Ok(other) => println!("Ignoring unexpected {} packet", other.name()),
+            Err(e) => println!("Dropping malformed packet: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Far more than the default scenario needs to finish
+    const MAX_TICKS: u64 = 2000;
+
+    pub(crate) fn default_scenario() -> Scenario {
+        Scenario::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/scenarios/default.toml"
+        ))
+        .unwrap()
+    }
+
+    // Settings that do not depend on the environment the tests run in
+    pub(crate) fn radio(scenario: &Scenario) -> RadioConfig {
+        RadioConfig {
+            seed: scenario.simulation.seed.unwrap_or(0),
+            ..RadioConfig::default()
+        }
+    }
+
+    // Steps towards the scenario's first target until the search is over, handing every
+    // input to `record` before it is applied
+    pub(crate) fn run(scenario: &Scenario, mut record: impl FnMut(&Input)) -> Engine {
+        let mut engine = Engine::new(scenario, DroneConfig::default(), radio(scenario));
+        let target = scenario
+            .targets
+            .first()
+            .map(|target| target.position.clone());
+        while !engine.is_finished() && engine.ticks() < MAX_TICKS {
+            let step = Input::Step {
+                target: target.clone(),
+            };
+            record(&step);
+            engine.apply(&step);
+        }
+        engine
+    }
+
+    #[test]
+    fn same_seed_same_outcome() {
+        let scenario = default_scenario();
+        let first = run(&scenario, |_| {}).outcome();
+        let second = run(&scenario, |_| {}).outcome();
+        assert!(first.finished);
+        assert_eq!(first, second);
+    }
+}
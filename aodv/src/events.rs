@@ -0,0 +1,94 @@
+use crate::persons::DetectionReport;
+use actix_web::web::Bytes;
+use futures_util::{stream, Stream, StreamExt};
+use mesh_proto::{DroneData, Mission};
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// Events a slow client can fall behind by before it starts missing some
+const CAPACITY: usize = 1024;
+
+// Something that happened in the simulation, pushed to the live view as it happens
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Position(DroneData),
+    Neighbors {
+        id: usize,
+        neighbors: Vec<usize>,
+    },
+    Packet {
+        sender: usize,
+        // None for a broadcast
+        receiver: Option<usize>,
+        kind: String,
+    },
+    DroneAdded(DroneData),
+    DroneRemoved {
+        id: usize,
+    },
+    Finished {
+        id: usize,
+    },
+    Mission {
+        id: usize,
+        mission: Mission,
+    },
+    MissionComplete {
+        id: usize,
+    },
+    Detection(DetectionReport),
+    Reset,
+}
+
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Events { sender }
+    }
+
+    pub fn publish(&self, event: Event) {
+        // Nobody may be listening, which is fine
+        let _ = self.sender.send(event);
+    }
+
+    // Server-Sent Events stream that starts with the given events and then follows
+    // everything published from now on
+    pub fn stream(
+        &self,
+        initial: Vec<Event>,
+    ) -> impl Stream<Item = Result<Bytes, Infallible>> + 'static {
+        let receiver = self.sender.subscribe();
+        let live = stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(missed)) => {
+                        println!("Live view fell behind, skipped {} events", missed)
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        stream::iter(initial)
+            .chain(live)
+            .map(|event| Ok(server_sent_event(&event)))
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events::new()
+    }
+}
+
+fn server_sent_event(event: &Event) -> Bytes {
+    let data = serde_json::to_string(event).expect("events always serialize");
+    Bytes::from(format!("data: {}\n\n", data))
+}
@@ -0,0 +1,151 @@
+use crate::scenario::DroneSpec;
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, File},
+    io,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Runs the prebuilt drone program as child processes, one per drone
+pub struct Launcher {
+    binary: PathBuf,
+    log_dir: PathBuf,
+    // Launched drones send a copy of every packet to the simulator
+    trace: bool,
+    // Where the drones send their search reports
+    base_station: Option<usize>,
+    children: BTreeMap<usize, Child>,
+}
+
+impl Launcher {
+    pub fn new(
+        log_dir: impl Into<PathBuf>,
+        trace: bool,
+        base_station: Option<usize>,
+    ) -> io::Result<Self> {
+        let log_dir = log_dir.into();
+        fs::create_dir_all(&log_dir)?;
+        Ok(Launcher {
+            binary: drone_binary()?,
+            log_dir,
+            trace,
+            base_station,
+            children: BTreeMap::new(),
+        })
+    }
+
+    // Starts a drone with its output written to drone-{id}.log in the log directory
+    pub fn spawn(&mut self, drone: &DroneSpec) -> io::Result<()> {
+        let id = drone.id;
+        let log = File::create(self.log_dir.join(format!("drone-{}.log", id)))?;
+        let mut command = Command::new(&self.binary);
+        command
+            .arg(id.to_string())
+            .arg(drone.position.x.to_string())
+            .arg(drone.position.y.to_string())
+            .env("DRONE_SPEED", drone.speed.to_string())
+            .env("DRONE_COMMUNICATION_RADIUS", drone.radio_range.to_string())
+            .env("DRONE_TRACE", self.trace.to_string())
+            .env("DRONE_RELAY", "true");
+        if let Some(base_station) = self.base_station {
+            command.env("DRONE_BASE_STATION", base_station.to_string());
+        }
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()?;
+        println!("Started drone {} (pid {})", id, child.id());
+        self.children.insert(id, child);
+        Ok(())
+    }
+
+    // Returns the drones whose process has exited since the last check
+    pub fn check(&mut self) -> Vec<usize> {
+        let mut exited = Vec::new();
+        for (id, child) in self.children.iter_mut() {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    println!("Drone {} exited with {}", id, status);
+                    exited.push(*id);
+                }
+                Ok(None) => {}
+                Err(e) => println!("Could not check drone {}: {}", id, e),
+            }
+        }
+        for id in &exited {
+            self.children.remove(id);
+        }
+        exited
+    }
+
+    // Kills the drone right away, like a crash. Returns false if it was not launched here.
+    pub fn kill(&mut self, id: usize) -> bool {
+        let Some(mut child) = self.children.remove(&id) else {
+            return false;
+        };
+        if let Err(e) = child.kill() {
+            println!("Could not kill drone {}: {}", id, e);
+        }
+        let _ = child.wait();
+        true
+    }
+
+    // Waits for a drone that has been told to shut down, killing it if it takes too long
+    pub fn stop(&mut self, id: usize, grace: Duration) {
+        let Some(mut child) = self.children.remove(&id) else {
+            return;
+        };
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline {
+            match child.try_wait() {
+                Ok(Some(_)) => return,
+                Ok(None) => thread::sleep(EXIT_POLL_INTERVAL),
+                Err(_) => break,
+            }
+        }
+        println!("Drone {} did not shut down, killing it", id);
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    pub fn kill_all(&mut self) {
+        for (id, mut child) in std::mem::take(&mut self.children) {
+            if let Err(e) = child.kill() {
+                println!("Could not stop drone {}: {}", id, e);
+            }
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for Launcher {
+    fn drop(&mut self) {
+        self.kill_all();
+    }
+}
+
+// The drone program is built into the same directory as the simulator, unless DRONE_BINARY
+// points somewhere else
+fn drone_binary() -> io::Result<PathBuf> {
+    let binary = match env::var_os("DRONE_BINARY") {
+        Some(path) => PathBuf::from(path),
+        None => env::current_exe()?.with_file_name(format!("drone{}", env::consts::EXE_SUFFIX)),
+    };
+    if !binary.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "drone program not found at {}, build it with cargo build --workspace",
+                binary.display()
+            ),
+        ));
+    }
+    Ok(binary)
+}
@@ -1,28 +1,105 @@
+mod capture;
+mod coverage;
+mod engine;
+mod events;
+mod launcher;
+mod partition;
+mod persons;
+mod radio;
+mod recording;
+mod scenario;
 mod simulator;
 
-use simulator::Coordinate;
-use simulator::Simulator;
+use capture::Capture;
+use drone::DroneConfig;
+use engine::Engine;
+use mesh_proto::addressing::HTTP_PORT;
+use mesh_proto::{Coordinate, Mission, Pattern};
+use partition::{Partitioning, Search};
+use radio::RadioConfig;
+use recording::{Input, Recorder, Recording};
+use scenario::{DroneSpec, Scenario};
+use serde::Deserialize;
+use simulator::{Simulator, SimulatorError};
+use std::env;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use actix_cors::Cors;
-use actix_web::{get, post, put, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{delete, get, post, put, web, App, HttpResponse, HttpServer, Responder};
+
+// How long to wait for a drone to answer with its routing table
+const ROUTING_TABLE_TIMEOUT: Duration = Duration::from_secs(1);
+const ROUTING_TABLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let sim = Simulator::new();
+    let args: Vec<String> = env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--replay") {
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("--replay needs the path to a recording");
+            std::process::exit(1);
+        };
+        run_replay(path);
+        return Ok(());
+    }
+    let scenario = load_scenario(&args);
+    if args.iter().any(|arg| arg == "--headless") {
+        run_headless(&args, &scenario);
+        return Ok(());
+    }
+
+    // --no-launch is for drones that are started by hand
+    let launch = !args.iter().any(|arg| arg == "--no-launch");
+    let radio = radio_config(&scenario);
+    let recorder = open_recorder(&args, &scenario, &DroneConfig::from_env(), &radio);
+    let sim = match Simulator::new(&scenario, launch, radio, open_capture(&args), recorder) {
+        Ok(sim) => sim,
+        Err(e) => {
+            eprintln!("Could not start the simulator: {}", e);
+            std::process::exit(1);
+        }
+    };
     println!("started");
 
-    HttpServer::new(move || {
+    let app_sim = sim.clone();
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(Cors::permissive())
             .service(test_connection)
             .service(do_step)
             .service(get_drones)
             .service(is_finished)
-            .data(sim.clone())
+            .service(get_scenario)
+            .service(update_drones)
+            .service(add_drone)
+            .service(remove_drone)
+            .service(kill_drone)
+            .service(move_drone)
+            .service(get_routing_table)
+            .service(get_targets)
+            .service(set_targets)
+            .service(clear_targets)
+            .service(reset)
+            .service(start_search)
+            .service(start_drone_mission)
+            .service(get_missions)
+            .service(cancel_mission)
+            .service(get_coverage)
+            .service(get_detections)
+            .service(get_reports)
+            .service(live_events)
+            .app_data(web::Data::new(app_sim.clone()))
+            .app_data(web::Data::new(scenario.clone()))
     })
-    .bind(("127.0.0.1", 8079))?
-    .run()
-    .await
+    .bind(("127.0.0.1", HTTP_PORT));
+
+    let result = match server {
+        Ok(server) => server.run().await,
+        Err(e) => Err(e),
+    };
+    sim.shutdown();
+    result
 }
 
 #[get("/test_connection")]
@@ -30,14 +107,14 @@ async fn test_connection() -> impl Responder {
     HttpResponse::Ok().json("Connected")
 }
 
+// Without a body the drones move towards the first target set with PUT /targets
 #[post("/do_step")]
-async fn do_step(sim: web::Data<Simulator>, coor: web::Json<Coordinate>) -> impl Responder {
+async fn do_step(sim: web::Data<Simulator>, coor: Option<web::Json<Coordinate>>) -> impl Responder {
     println!("doing step");
-    let x = coor.x;
-    let y = coor.y;
-
-    sim.do_step(x, y);
-    HttpResponse::Ok()
+    match sim.do_step(coor.map(web::Json::into_inner)) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(e),
+    }
 }
 
 #[get("/get_drones")]
@@ -52,8 +129,482 @@ async fn is_finished(sim: web::Data<Simulator>) -> impl Responder {
     HttpResponse::Ok().json(sim.is_finished())
 }
 
+#[get("/scenario")]
+async fn get_scenario(scenario: web::Data<Scenario>) -> impl Responder {
+    HttpResponse::Ok().json(scenario.public())
+}
+
 #[put("/update")]
 async fn update_drones(sim: web::Data<Simulator>) -> impl Responder {
-    sim.update_drones();
+    match sim.update_drones() {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewDrone {
+    // The lowest free id is used if none is given
+    id: Option<usize>,
+    position: Coordinate,
+    speed: Option<f32>,
+    radio_range: Option<f32>,
+    battery: Option<f32>,
+}
+
+#[post("/drones")]
+async fn add_drone(sim: web::Data<Simulator>, drone: web::Json<NewDrone>) -> impl Responder {
+    let drone = drone.into_inner();
+    let id = match drone.id {
+        Some(id) => id,
+        None => match sim.free_id() {
+            Ok(id) => id,
+            Err(e) => return error_response(e),
+        },
+    };
+    let mut spec = DroneSpec::new(id, drone.position);
+    spec.speed = drone.speed.unwrap_or(spec.speed);
+    spec.radio_range = drone.radio_range.unwrap_or(spec.radio_range);
+    spec.battery = drone.battery.unwrap_or(spec.battery);
+
+    match sim.add_drone(spec) {
+        Ok(spec) => HttpResponse::Created().json(spec),
+        Err(e) => error_response(e),
+    }
+}
+
+// Lets the drone leave the swarm on its own terms
+#[delete("/drones/{id}")]
+async fn remove_drone(sim: web::Data<Simulator>, id: web::Path<usize>) -> impl Responder {
+    let id = id.into_inner();
+    let sim = sim.get_ref().clone();
+    match web::block(move || sim.remove_drone(id)).await {
+        Ok(Ok(())) => HttpResponse::NoContent().finish(),
+        Ok(Err(e)) => error_response(e),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+// Simulates a crash, the other drones are not told
+#[post("/drones/{id}/kill")]
+async fn kill_drone(sim: web::Data<Simulator>, id: web::Path<usize>) -> impl Responder {
+    match sim.kill_drone(id.into_inner()) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[post("/drones/{id}/move")]
+async fn move_drone(
+    sim: web::Data<Simulator>,
+    id: web::Path<usize>,
+    target: web::Json<Coordinate>,
+) -> impl Responder {
+    match sim.move_drone(id.into_inner(), target.into_inner()) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/drones/{id}/routes")]
+async fn get_routing_table(sim: web::Data<Simulator>, id: web::Path<usize>) -> impl Responder {
+    let id = id.into_inner();
+    let requested = match sim.request_routing_table(id) {
+        Ok(requested) => requested,
+        Err(e) => return error_response(e),
+    };
+    while requested.elapsed() < ROUTING_TABLE_TIMEOUT {
+        if let Some(report) = sim.routing_table(id, requested) {
+            return HttpResponse::Ok().json(report);
+        }
+        actix_web::rt::time::sleep(ROUTING_TABLE_POLL_INTERVAL).await;
+    }
+    HttpResponse::GatewayTimeout().body(format!("drone {} did not answer", id))
+}
+
+#[get("/targets")]
+async fn get_targets(sim: web::Data<Simulator>) -> impl Responder {
+    HttpResponse::Ok().json(sim.targets())
+}
+
+#[put("/targets")]
+async fn set_targets(
+    sim: web::Data<Simulator>,
+    targets: web::Json<Vec<Coordinate>>,
+) -> impl Responder {
+    sim.set_targets(targets.into_inner());
+    HttpResponse::Ok().finish()
+}
+
+#[delete("/targets")]
+async fn clear_targets(sim: web::Data<Simulator>) -> impl Responder {
+    sim.set_targets(Vec::new());
+    HttpResponse::NoContent().finish()
+}
+
+#[post("/reset")]
+async fn reset(sim: web::Data<Simulator>) -> impl Responder {
+    let sim = sim.get_ref().clone();
+    match web::block(move || sim.reset()).await {
+        Ok(Ok(())) => HttpResponse::Ok().finish(),
+        Ok(Err(e)) => error_response(e),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewSearch {
+    // The scenario's search area is used if none is given
+    area: Option<Vec<Coordinate>>,
+    pattern: Pattern,
+    spacing: f32,
+    #[serde(default)]
+    partitioning: Partitioning,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewMission {
+    area: Option<Vec<Coordinate>>,
+    pattern: Pattern,
+    spacing: f32,
+}
+
+fn mission_area(
+    area: Option<Vec<Coordinate>>,
+    scenario: &Scenario,
+) -> Result<Vec<Coordinate>, SimulatorError> {
+    match (area, &scenario.search_area) {
+        (Some(area), _) => Ok(area),
+        (None, Some(search_area)) => Ok(search_area.polygon.clone()),
+        (None, None) => Err(SimulatorError::InvalidMission(
+            "no area given and the scenario has no search area".to_string(),
+        )),
+    }
+}
+
+// The drones split the area between them, after this POST /do_step moves them along their
+// paths
+#[post("/mission")]
+async fn start_search(
+    sim: web::Data<Simulator>,
+    scenario: web::Data<Scenario>,
+    search: web::Json<NewSearch>,
+) -> impl Responder {
+    let search = search.into_inner();
+    let result = mission_area(search.area, &scenario).and_then(|area| {
+        sim.start_search(Search {
+            area,
+            pattern: search.pattern,
+            spacing: search.spacing,
+            partitioning: search.partitioning,
+            obstacles: scenario.obstacle_polygons(),
+        })
+    });
+    match result {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[post("/drones/{id}/mission")]
+async fn start_drone_mission(
+    sim: web::Data<Simulator>,
+    scenario: web::Data<Scenario>,
+    id: web::Path<usize>,
+    mission: web::Json<NewMission>,
+) -> impl Responder {
+    let mission = mission.into_inner();
+    let result = mission_area(mission.area, &scenario).and_then(|area| {
+        sim.start_mission(
+            id.into_inner(),
+            Mission {
+                area,
+                pattern: mission.pattern,
+                spacing: mission.spacing,
+                obstacles: scenario.obstacle_polygons(),
+            },
+        )
+    });
+    match result {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+// The missions still being flown, by drone
+#[get("/mission")]
+async fn get_missions(sim: web::Data<Simulator>) -> impl Responder {
+    HttpResponse::Ok().json(sim.missions())
+}
+
+#[delete("/mission")]
+async fn cancel_mission(sim: web::Data<Simulator>) -> impl Responder {
+    sim.cancel_mission();
+    HttpResponse::NoContent().finish()
+}
+
+#[get("/coverage")]
+async fn get_coverage(sim: web::Data<Simulator>) -> impl Responder {
+    match sim.coverage() {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response(e),
+    }
+}
+
+// Every person the drones' thermal sensors have reported, including false positives
+#[get("/detections")]
+async fn get_detections(sim: web::Data<Simulator>) -> impl Responder {
+    HttpResponse::Ok().json(sim.detections())
+}
+
+// The search reports that have reached the base station
+#[get("/reports")]
+async fn get_reports(sim: web::Data<Simulator>) -> impl Responder {
+    HttpResponse::Ok().json(sim.reports())
+}
+
+// Server-Sent Events stream of positions, neighbor changes, sent packets and the finished
+// state, starting with the drones as they are now
+#[get("/events")]
+async fn live_events(sim: web::Data<Simulator>) -> impl Responder {
     HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(sim.events().stream(sim.snapshot()))
+}
+
+fn error_response(e: SimulatorError) -> HttpResponse {
+    let mut response = match e {
+        SimulatorError::UnknownDrone(_) | SimulatorError::NoMission => HttpResponse::NotFound(),
+        SimulatorError::DuplicateDrone(_) | SimulatorError::NotLaunched => HttpResponse::Conflict(),
+        SimulatorError::InvalidDrone(_)
+        | SimulatorError::NoTarget
+        | SimulatorError::InvalidMission(_) => HttpResponse::BadRequest(),
+        SimulatorError::Io(_) => HttpResponse::InternalServerError(),
+    };
+    response.body(e.to_string())
+}
+
+// Loads the file given with --scenario, or droner.txt and kobling_droner.txt without it
+fn load_scenario(args: &[String]) -> Scenario {
+    let scenario = match args.iter().position(|arg| arg == "--scenario") {
+        Some(i) => {
+            let Some(path) = args.get(i + 1) else {
+                eprintln!("--scenario needs the path to a scenario file");
+                std::process::exit(1);
+            };
+            Scenario::load(path)
+        }
+        None => Scenario::from_legacy_files(),
+    };
+    match scenario {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            eprintln!("Invalid scenario: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Opens the capture files given with --capture PATH, writing PATH.jsonl and PATH.pcap
+fn open_capture(args: &[String]) -> Option<Capture> {
+    let i = args.iter().position(|arg| arg == "--capture")?;
+    let Some(path) = args.get(i + 1) else {
+        eprintln!("--capture needs the path to write the capture to");
+        std::process::exit(1);
+    };
+    match Capture::create(path) {
+        Ok(capture) => Some(capture),
+        Err(e) => {
+            eprintln!("Could not create capture {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Starts a recording with --record FILE
+fn open_recorder(
+    args: &[String],
+    scenario: &Scenario,
+    drone_config: &DroneConfig,
+    radio: &RadioConfig,
+) -> Option<Recorder> {
+    let i = args.iter().position(|arg| arg == "--record")?;
+    let Some(path) = args.get(i + 1) else {
+        eprintln!("--record needs the path to write the recording to");
+        std::process::exit(1);
+    };
+    match Recorder::create(path, scenario, drone_config, radio) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            eprintln!("Could not create recording {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The radio settings from the environment, with the seed from the scenario if it has one
+fn radio_config(scenario: &Scenario) -> RadioConfig {
+    let mut radio = RadioConfig::from_env();
+    if let Some(seed) = scenario.simulation.seed {
+        radio.seed = seed;
+    }
+    radio
+}
+
+fn parse_arg<T: FromStr>(value: &str, what: &str) -> T {
+    match value.parse() {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("Could not parse {}: {}", what, value);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Usage: aodv --headless [--scenario FILE] [--target X Y] [--max-ticks N] [--seed N]
+//        [--capture PATH] [--record FILE]
+fn run_headless(args: &[String], scenario: &Scenario) {
+    let mut target = scenario
+        .targets
+        .first()
+        .map(|target| target.position.clone());
+    let mut max_ticks = scenario.simulation.max_ticks;
+    let mut radio = radio_config(scenario);
+    let mut verbose = false;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--headless" => {}
+            // Prints what every drone does, instead of just the outcome
+            "--verbose" => verbose = true,
+            "--scenario" | "--capture" | "--record" => i += 1,
+            "--target" if i + 2 < args.len() => {
+                target = Some(Coordinate {
+                    x: parse_arg(&args[i + 1], "target (x)"),
+                    y: parse_arg(&args[i + 2], "target (y)"),
+                });
+                i += 2;
+            }
+            "--max-ticks" if i + 1 < args.len() => {
+                max_ticks = parse_arg(&args[i + 1], "max ticks");
+                i += 1;
+            }
+            "--seed" if i + 1 < args.len() => {
+                radio.seed = parse_arg(&args[i + 1], "seed");
+                i += 1;
+            }
+            other => {
+                eprintln!("Unknown argument: {}", other);
+                eprintln!(
+                    "Usage: aodv --headless [--scenario FILE] [--target X Y] [--max-ticks N] [--seed N] [--verbose] [--capture PATH] [--record FILE]"
+                );
+                std::process::exit(1);
+            }
+        }
+        i += 1;
+    }
+
+    let Some(target) = target else {
+        eprintln!("No target, add one to the scenario or pass --target X Y");
+        std::process::exit(1);
+    };
+    let drone_config = DroneConfig {
+        verbose,
+        ..DroneConfig::from_env()
+    };
+    let mut recorder = open_recorder(args, scenario, &drone_config, &radio);
+    let mut engine = Engine::new(scenario, drone_config, radio);
+    if let Some(capture) = open_capture(args) {
+        engine.set_capture(capture);
+    }
+
+    let started = Instant::now();
+    while !engine.is_finished() && engine.ticks() < max_ticks {
+        let step = Input::Step {
+            target: Some(target.clone()),
+        };
+        if let Some(recorder) = &mut recorder {
+            if let Err(e) = recorder.record(&step) {
+                println!("Could not record step: {}", e);
+            }
+        }
+        engine.apply(&step);
+    }
+    let finished = engine.is_finished();
+    let outcome = engine.outcome();
+    if let Some(recorder) = &mut recorder {
+        if let Err(e) = recorder.finish(&outcome) {
+            println!("Could not record the outcome: {}", e);
+        }
+    }
+    println!(
+        "Finished: {} after {} ticks ({:?} simulated, {:?} real)",
+        finished,
+        engine.ticks(),
+        engine.elapsed(),
+        started.elapsed()
+    );
+    let stats = engine.radio_stats();
+    println!(
+        "Radio: {} sent, {} delivered, {} lost",
+        stats.sent, stats.delivered, stats.lost
+    );
+    let (found, persons) = engine.persons_found();
+    let false_positives = engine
+        .detections()
+        .iter()
+        .filter(|detection| detection.person.is_none())
+        .count();
+    println!(
+        "Thermal sensors: {} of {} missing persons found, {} false positives",
+        found, persons, false_positives
+    );
+    if let Some(capture) = engine.capture() {
+        println!("Captured {} packets", capture.packets());
+    }
+    for drone in engine.positions() {
+        println!("Drone {}: x: {}, y: {}", drone.id, drone.x, drone.y);
+    }
+    println!("Digest: {}", outcome.digest);
+}
+
+// Runs the inputs of a recording on the engine. If the recording has an outcome the
+// replay has to end in the same state, otherwise the process exits with an error.
+fn run_replay(path: &str) {
+    let recording = match Recording::load(path) {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("Invalid recording: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let engine = Engine::replay(&recording);
+    let outcome = engine.outcome();
+    println!(
+        "Replayed {} inputs: finished: {} after {} ticks",
+        recording.inputs.len(),
+        outcome.finished,
+        outcome.ticks
+    );
+    for drone in engine.positions() {
+        println!("Drone {}: x: {}, y: {}", drone.id, drone.x, drone.y);
+    }
+    println!("Digest: {}", outcome.digest);
+
+    match recording.outcome {
+        Some(recorded) if recorded == outcome => println!("Matches the recorded run"),
+        Some(recorded) => {
+            eprintln!(
+                "Differs from the recorded run: finished: {} after {} ticks, digest {}",
+                recorded.finished, recorded.ticks, recorded.digest
+            );
+            std::process::exit(1);
+        }
+        None => {}
+    }
 }
@@ -0,0 +1,395 @@
+use mesh_proto::geometry::{area, bounds, clip};
+use mesh_proto::{Coordinate, Mission, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::scenario::check_mission;
+
+// Bisection steps when looking for where to cut a strip, plenty for f32
+const CUT_ITERATIONS: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Partitioning {
+    // Each drone gets the part of the area that is closer to it than to any other drone
+    #[default]
+    Voronoi,
+    // Strips of equal size, handed out from left to right
+    Strips,
+    // Strips sized by how far each drone can fly, battery times speed
+    Weighted,
+}
+
+// A drone as the partitioning sees it
+pub struct Searcher {
+    pub id: usize,
+    pub position: Coordinate,
+    pub speed: f32,
+    pub battery: f32,
+}
+
+// An area searched by the whole swarm, split into one mission per drone
+#[derive(Debug, Clone, Serialize)]
+pub struct Search {
+    pub area: Vec<Coordinate>,
+    pub pattern: Pattern,
+    pub spacing: f32,
+    pub partitioning: Partitioning,
+    // Taken to be inside the area and apart from each other
+    pub obstacles: Vec<Vec<Coordinate>>,
+}
+
+impl Search {
+    // The whole search as one mission
+    pub fn mission(&self) -> Mission {
+        self.mission_for(self.area.clone())
+    }
+
+    // Drones whose share of the area is empty get no mission. Cutting the area adds
+    // corners, so a share can be too big to send even when the whole area is not.
+    pub fn assign(&self, searchers: &[Searcher]) -> Result<BTreeMap<usize, Mission>, String> {
+        let shares = match self.partitioning {
+            Partitioning::Voronoi => voronoi(&self.area, searchers),
+            Partitioning::Strips => strips(&self.area, &self.obstacles, searchers, |_| 1.0),
+            Partitioning::Weighted => strips(&self.area, &self.obstacles, searchers, |searcher| {
+                searcher.battery * searcher.speed
+            }),
+        };
+        shares
+            .into_iter()
+            .filter(|(_, share)| share.len() >= 3 && area(share) > 0.0)
+            .map(|(id, share)| {
+                let mission = self.mission_for(share);
+                check_mission(&mission)
+                    .map_err(|reason| format!("share of drone {}: {}", id, reason))?;
+                Ok((id, mission))
+            })
+            .collect()
+    }
+
+    // Each mission only carries the obstacles that reach into its area
+    fn mission_for(&self, area: Vec<Coordinate>) -> Mission {
+        let (min, max) = bounds(&area);
+        let obstacles = self
+            .obstacles
+            .iter()
+            .filter(|obstacle| {
+                let (obstacle_min, obstacle_max) = bounds(obstacle);
+                obstacle_min.x <= max.x
+                    && obstacle_max.x >= min.x
+                    && obstacle_min.y <= max.y
+                    && obstacle_max.y >= min.y
+            })
+            .cloned()
+            .collect();
+        Mission {
+            area,
+            pattern: self.pattern,
+            spacing: self.spacing,
+            obstacles,
+        }
+    }
+}
+
+// Cuts the area along the line halfway between each pair of drones. Two drones in the
+// same spot cannot be told apart, so the one with the lowest id gets the cell.
+fn voronoi(area: &[Coordinate], searchers: &[Searcher]) -> BTreeMap<usize, Vec<Coordinate>> {
+    let mut shares = BTreeMap::new();
+    for searcher in searchers {
+        let own = &searcher.position;
+        let mut cell = area.to_vec();
+        for other in searchers.iter().filter(|other| other.id != searcher.id) {
+            let position = &other.position;
+            let normal = Coordinate {
+                x: position.x - own.x,
+                y: position.y - own.y,
+            };
+            if normal.x == 0.0 && normal.y == 0.0 {
+                if other.id < searcher.id {
+                    cell.clear();
+                    break;
+                }
+                continue;
+            }
+            let offset =
+                (position.x * position.x + position.y * position.y - own.x * own.x - own.y * own.y)
+                    / 2.0;
+            cell = clip(&cell, &normal, offset);
+        }
+        shares.insert(searcher.id, cell);
+    }
+    shares
+}
+
+// Cuts the area into vertical strips with sizes in proportion to the weights, not counting
+// the obstacles. The drones get the strips in the order they are in from left to right, so
+// nobody has to cross another drone's strip to get to their own.
+fn strips(
+    polygon: &[Coordinate],
+    obstacles: &[Vec<Coordinate>],
+    searchers: &[Searcher],
+    weight: impl Fn(&Searcher) -> f32,
+) -> BTreeMap<usize, Vec<Coordinate>> {
+    let mut order: Vec<&Searcher> = searchers.iter().collect();
+    order.sort_by(|a, b| a.position.x.total_cmp(&b.position.x).then(a.id.cmp(&b.id)));
+    let mut weights: Vec<f32> = order
+        .iter()
+        .map(|searcher| weight(searcher).max(0.0))
+        .collect();
+    if weights.iter().sum::<f32>() <= 0.0 {
+        weights = vec![1.0; order.len()];
+    }
+    let total_weight: f32 = weights.iter().sum();
+
+    let (min, max) = bounds(polygon);
+    let size = free_area(polygon, obstacles, max.x);
+    let mut shares = BTreeMap::new();
+    let mut left = min.x;
+    let mut weight_so_far = 0.0;
+    for (index, searcher) in order.iter().enumerate() {
+        weight_so_far += weights[index];
+        let right = if index + 1 == order.len() {
+            max.x
+        } else {
+            cut(
+                polygon,
+                obstacles,
+                min.x,
+                max.x,
+                size * weight_so_far / total_weight,
+            )
+        };
+        let strip = clip(
+            &clip(polygon, &Coordinate { x: -1.0, y: 0.0 }, -left),
+            &Coordinate { x: 1.0, y: 0.0 },
+            right,
+        );
+        shares.insert(searcher.id, strip);
+        left = right;
+    }
+    shares
+}
+
+// Where to cut the area so the part left of the cut has the given size
+fn cut(
+    polygon: &[Coordinate],
+    obstacles: &[Vec<Coordinate>],
+    min_x: f32,
+    max_x: f32,
+    size: f32,
+) -> f32 {
+    let (mut low, mut high) = (min_x, max_x);
+    for _ in 0..CUT_ITERATIONS {
+        let middle = (low + high) / 2.0;
+        if free_area(polygon, obstacles, middle) < size {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Size of the part of the area left of x, less the obstacles in it
+fn free_area(polygon: &[Coordinate], obstacles: &[Vec<Coordinate>], x: f32) -> f32 {
+    let left = |polygon: &[Coordinate]| area(&clip(polygon, &Coordinate { x: 1.0, y: 0.0 }, x));
+    let blocked: f32 = obstacles.iter().map(|obstacle| left(obstacle)).sum();
+    (left(polygon) - blocked).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use drone::mission::coverage_path;
+    use mesh_proto::geometry::{contains, distance};
+
+    // A pentagon, so the cuts do not all line up with the corners
+    fn area_to_search() -> Vec<Coordinate> {
+        [
+            (0.0, 0.0),
+            (1000.0, 0.0),
+            (1000.0, 500.0),
+            (600.0, 700.0),
+            (0.0, 600.0),
+        ]
+        .iter()
+        .map(|&(x, y)| Coordinate { x, y })
+        .collect()
+    }
+
+    fn searchers() -> Vec<Searcher> {
+        [
+            (100.0, 100.0, 1.0),
+            (800.0, 150.0, 2.0),
+            (300.0, 500.0, 3.0),
+            (700.0, 550.0, 2.0),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(id, &(x, y, battery))| Searcher {
+            id,
+            position: Coordinate { x, y },
+            speed: 1.0,
+            battery,
+        })
+        .collect()
+    }
+
+    fn search(partitioning: Partitioning, pattern: Pattern) -> Search {
+        Search {
+            area: area_to_search(),
+            pattern,
+            spacing: 20.0,
+            partitioning,
+            obstacles: Vec::new(),
+        }
+    }
+
+    // The shares add up to the area, and every point of it is in exactly one share.
+    // The points are off the round numbers the cuts fall on.
+    fn assert_tiles(shares: &BTreeMap<usize, Mission>) {
+        let whole = area(&area_to_search());
+        let total: f32 = shares.values().map(|share| area(&share.area)).sum();
+        assert!(
+            (total - whole).abs() < whole * 1e-4,
+            "{} != {}",
+            total,
+            whole
+        );
+
+        for i in 0..50 {
+            for j in 0..35 {
+                let point = Coordinate {
+                    x: i as f32 * 20.0 + 7.3,
+                    y: j as f32 * 20.0 + 3.7,
+                };
+                if !contains(&area_to_search(), &point) {
+                    continue;
+                }
+                let owners = shares
+                    .values()
+                    .filter(|share| contains(&share.area, &point))
+                    .count();
+                assert_eq!(owners, 1, "{:?} is in {} shares", point, owners);
+            }
+        }
+    }
+
+    #[test]
+    fn voronoi_cells_tile_the_area() {
+        let shares = search(Partitioning::Voronoi, Pattern::Lawnmower)
+            .assign(&searchers())
+            .unwrap();
+        assert_eq!(shares.len(), 4);
+        assert_tiles(&shares);
+        // Each drone gets the cell it is in
+        for searcher in searchers() {
+            assert!(contains(&shares[&searcher.id].area, &searcher.position));
+        }
+    }
+
+    #[test]
+    fn strips_tile_the_area_in_equal_sizes() {
+        let shares = search(Partitioning::Strips, Pattern::Lawnmower)
+            .assign(&searchers())
+            .unwrap();
+        assert_eq!(shares.len(), 4);
+        assert_tiles(&shares);
+        let quarter = area(&area_to_search()) / 4.0;
+        for share in shares.values() {
+            assert!((area(&share.area) - quarter).abs() < quarter * 1e-3);
+        }
+    }
+
+    #[test]
+    fn weighted_strips_are_in_proportion_to_the_weights() {
+        let searchers = searchers();
+        let shares = search(Partitioning::Weighted, Pattern::Lawnmower)
+            .assign(&searchers)
+            .unwrap();
+        assert_tiles(&shares);
+        let whole = area(&area_to_search());
+        let total_weight: f32 = searchers.iter().map(|s| s.battery * s.speed).sum();
+        for searcher in &searchers {
+            let expected = whole * searcher.battery * searcher.speed / total_weight;
+            let size = area(&shares[&searcher.id].area);
+            assert!(
+                (size - expected).abs() < expected * 1e-3,
+                "{} != {}",
+                size,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn cut_splits_off_the_given_size() {
+        let polygon = area_to_search();
+        let x = cut(&polygon, &[], 0.0, 1000.0, 100_000.0);
+        let left = clip(&polygon, &Coordinate { x: 1.0, y: 0.0 }, x);
+        assert!((area(&left) - 100_000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn strips_leave_out_the_obstacles() {
+        let obstacle: Vec<Coordinate> = [(100.0, 100.0), (300.0, 100.0), (300.0, 400.0)]
+            .iter()
+            .map(|&(x, y)| Coordinate { x, y })
+            .collect();
+        let search = Search {
+            obstacles: vec![obstacle.clone()],
+            ..search(Partitioning::Strips, Pattern::Lawnmower)
+        };
+        let shares = search.assign(&searchers()).unwrap();
+        assert_tiles(&shares);
+        let quarter = (area(&area_to_search()) - area(&obstacle)) / 4.0;
+        for share in shares.values() {
+            // The part of the obstacle between the sides of the strip
+            let (min, max) = bounds(&share.area);
+            let blocked = free_area(&obstacle, &[], max.x) - free_area(&obstacle, &[], min.x);
+            let size = area(&share.area) - blocked;
+            assert!(
+                (size - quarter).abs() < quarter * 1e-3,
+                "{} != {}",
+                size,
+                quarter
+            );
+        }
+    }
+
+    // Otherwise the drones would search each other's shares again
+    #[test]
+    fn every_pattern_stays_in_its_share() {
+        for partitioning in [Partitioning::Voronoi, Partitioning::Strips] {
+            for pattern in [
+                Pattern::Lawnmower,
+                Pattern::ExpandingSquare,
+                Pattern::Sector,
+            ] {
+                let shares = search(partitioning, pattern).assign(&searchers()).unwrap();
+                for (id, share) in &shares {
+                    let path = coverage_path(share, &searchers()[*id].position);
+                    assert!(!path.is_empty());
+                    for waypoint in &path {
+                        let on_edge = share
+                            .area
+                            .iter()
+                            .zip(share.area.iter().cycle().skip(1))
+                            .any(|(a, b)| {
+                                distance(a, waypoint) + distance(waypoint, b) - distance(a, b)
+                                    < 1e-2
+                            });
+                        assert!(
+                            contains(&share.area, waypoint) || on_edge,
+                            "{:?} {:?} of drone {} leaves its share at {:?}",
+                            partitioning,
+                            pattern,
+                            id,
+                            waypoint
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::scenario::Scenario;
+use drone::DroneConfig;
+use mesh_proto::geometry::distance;
+use mesh_proto::{Coordinate, Packet};
+use serde::Serialize;
+
+// A person a drone's thermal sensor reported, and who it was if it was anyone
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectionReport {
+    pub id: usize,
+    pub position: Coordinate,
+    pub confidence: f32,
+    // Index of the missing person in the scenario, None for a false positive
+    pub person: Option<usize>,
+}
+
+// Where the missing people are. Only the simulator knows this, the drones just get the
+// people their thermal camera has in view.
+pub struct MissingPersons {
+    positions: Vec<Coordinate>,
+    footprint_radius: f32,
+}
+
+impl MissingPersons {
+    pub fn new(scenario: &Scenario, config: &DroneConfig) -> Self {
+        MissingPersons {
+            positions: scenario
+                .missing_persons
+                .iter()
+                .map(|person| person.position.clone())
+                .collect(),
+            footprint_radius: config.footprint_radius(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    // The camera frame a drone at this position gets
+    pub fn frame(&self, position: &Coordinate) -> Packet {
+        Packet::ThermalFrame {
+            sources: self
+                .positions
+                .iter()
+                .filter(|person| distance(person, position) <= self.footprint_radius)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    // Matches a detection to the closest person within a footprint of it
+    pub fn identify(&self, id: usize, position: Coordinate, confidence: f32) -> DetectionReport {
+        let person = self
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(index, person)| (index, distance(person, &position)))
+            .filter(|(_, offset)| *offset <= self.footprint_radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index);
+        DetectionReport {
+            id,
+            position,
+            confidence,
+            person,
+        }
+    }
+}
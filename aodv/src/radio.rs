@@ -0,0 +1,400 @@
+use drone::config::read_env;
+use mesh_proto::DroneData;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, f64::consts::PI, time::Duration};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RadioConfig {
+    pub seed: u64,
+    pub tx_power_dbm: f64,
+    // Path loss at the reference distance of one unit
+    pub reference_loss_db: f64,
+    pub path_loss_exponent: f64,
+    // Standard deviation of the random fading added to every packet
+    pub shadowing_db: f64,
+    // Packets received below this power are lost
+    pub sensitivity_dbm: f64,
+    pub latency: Duration,
+    // Every directed link gets a fixed extra delay of up to this much, on top of the latency
+    #[serde(default)]
+    pub link_latency: Duration,
+    pub jitter: Duration,
+    // Bits per second a drone can transmit, zero for no limit
+    pub bandwidth_bps: u64,
+    // Every directed link gets a fixed gain offset of up to this many dB either way
+    pub asymmetry_db: f64,
+}
+
+impl Default for RadioConfig {
+    // Calibrated so a packet sent over the drones' communication radius of 150 gets
+    // through about half of the time
+    fn default() -> Self {
+        RadioConfig {
+            seed: 0,
+            tx_power_dbm: 0.0,
+            reference_loss_db: 40.0,
+            path_loss_exponent: 3.0,
+            shadowing_db: 4.0,
+            sensitivity_dbm: -105.0,
+            latency: Duration::from_millis(2),
+            link_latency: Duration::from_millis(3),
+            jitter: Duration::from_millis(1),
+            bandwidth_bps: 1_000_000,
+            asymmetry_db: 0.0,
+        }
+    }
+}
+
+impl RadioConfig {
+    // Reads overrides from RADIO_SEED, RADIO_TX_POWER_DBM, RADIO_REFERENCE_LOSS_DB,
+    // RADIO_PATH_LOSS_EXPONENT, RADIO_SHADOWING_DB, RADIO_SENSITIVITY_DBM, RADIO_LATENCY_MS,
+    // RADIO_LINK_LATENCY_MS, RADIO_JITTER_MS, RADIO_BANDWIDTH_BPS and RADIO_ASYMMETRY_DB,
+    // falling back to the defaults.
+    pub fn from_env() -> Self {
+        let default = RadioConfig::default();
+        RadioConfig {
+            seed: read_env("RADIO_SEED").unwrap_or(default.seed),
+            tx_power_dbm: read_env("RADIO_TX_POWER_DBM").unwrap_or(default.tx_power_dbm),
+            reference_loss_db: read_env("RADIO_REFERENCE_LOSS_DB")
+                .unwrap_or(default.reference_loss_db),
+            path_loss_exponent: read_env("RADIO_PATH_LOSS_EXPONENT")
+                .unwrap_or(default.path_loss_exponent),
+            shadowing_db: read_env("RADIO_SHADOWING_DB").unwrap_or(default.shadowing_db),
+            sensitivity_dbm: read_env("RADIO_SENSITIVITY_DBM").unwrap_or(default.sensitivity_dbm),
+            latency: read_env("RADIO_LATENCY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.latency),
+            link_latency: read_env("RADIO_LINK_LATENCY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.link_latency),
+            jitter: read_env("RADIO_JITTER_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.jitter),
+            bandwidth_bps: read_env("RADIO_BANDWIDTH_BPS").unwrap_or(default.bandwidth_bps),
+            asymmetry_db: read_env("RADIO_ASYMMETRY_DB").unwrap_or(default.asymmetry_db),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RadioStats {
+    pub sent: u64,
+    pub delivered: u64,
+    pub lost: u64,
+}
+
+// Decides which transmissions reach which drones and when. All randomness comes from
+// one seeded generator, so a run only depends on the seed and the order of transmissions.
+pub struct RadioModel {
+    config: RadioConfig,
+    rng: StdRng,
+    link_offsets: BTreeMap<(usize, usize), f64>,
+    link_latencies: BTreeMap<(usize, usize), Duration>,
+    // When each drone's transmitter is free again
+    busy_until: BTreeMap<usize, Duration>,
+    // Packets in flight keyed by arrival time and a counter that keeps send order
+    in_flight: BTreeMap<(Duration, u64), (usize, Vec<u8>)>,
+    next_id: u64,
+    stats: RadioStats,
+}
+
+impl RadioModel {
+    pub fn new(config: RadioConfig) -> Self {
+        RadioModel {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            link_offsets: BTreeMap::new(),
+            link_latencies: BTreeMap::new(),
+            busy_until: BTreeMap::new(),
+            in_flight: BTreeMap::new(),
+            next_id: 0,
+            stats: RadioStats::default(),
+        }
+    }
+
+    // Sends one transmission from the sender to each of the receivers. A broadcast uses
+    // the transmitter once but every receiver fades and loses packets independently.
+    pub fn transmit(&mut self, now: Duration, from: &DroneData, to: &[DroneData], data: &[u8]) {
+        let start = self
+            .busy_until
+            .get(&from.id)
+            .copied()
+            .unwrap_or_default()
+            .max(now);
+        let end = start + self.transmission_time(data.len());
+        self.busy_until.insert(from.id, end);
+
+        for receiver in to {
+            self.stats.sent += 1;
+            if !self.received(from, receiver) {
+                self.stats.lost += 1;
+                continue;
+            }
+
+            let arrival =
+                end + self.config.latency + self.link_latency(from.id, receiver.id) + self.jitter();
+            self.in_flight
+                .insert((arrival, self.next_id), (receiver.id, data.to_vec()));
+            self.next_id += 1;
+        }
+    }
+
+    // Removes the packets that have arrived by now, in arrival order
+    pub fn arrived(&mut self, now: Duration) -> Vec<(usize, Vec<u8>)> {
+        let mut arrived = Vec::new();
+        while let Some(entry) = self.in_flight.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            arrived.push(entry.remove());
+        }
+        self.stats.delivered += arrived.len() as u64;
+        arrived
+    }
+
+    pub fn next_arrival(&self) -> Option<Duration> {
+        self.in_flight.keys().next().map(|(arrival, _)| *arrival)
+    }
+
+    pub fn stats(&self) -> &RadioStats {
+        &self.stats
+    }
+
+    fn transmission_time(&self, size: usize) -> Duration {
+        if self.config.bandwidth_bps == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((size * 8) as f64 / self.config.bandwidth_bps as f64)
+    }
+
+    fn jitter(&mut self) -> Duration {
+        if self.config.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+        self.rng.gen_range(Duration::ZERO..=self.config.jitter)
+    }
+
+    // Log-distance path loss with log-normal shadowing
+    fn received(&mut self, from: &DroneData, to: &DroneData) -> bool {
+        let distance = (((from.x - to.x).powi(2) + (from.y - to.y).powi(2)) as f64)
+            .sqrt()
+            .max(1.0);
+        let path_loss = self.config.reference_loss_db
+            + 10.0 * self.config.path_loss_exponent * distance.log10();
+        let power = self.config.tx_power_dbm - path_loss
+            + self.link_offset(from.id, to.id)
+            + self.gaussian() * self.config.shadowing_db;
+        power >= self.config.sensitivity_dbm
+    }
+
+    fn link_offset(&mut self, from: usize, to: usize) -> f64 {
+        if self.config.asymmetry_db == 0.0 {
+            return 0.0;
+        }
+        let asymmetry = self.config.asymmetry_db;
+        let rng = &mut self.rng;
+        *self
+            .link_offsets
+            .entry((from, to))
+            .or_insert_with(|| rng.gen_range(-asymmetry..=asymmetry))
+    }
+
+    fn link_latency(&mut self, from: usize, to: usize) -> Duration {
+        if self.config.link_latency.is_zero() {
+            return Duration::ZERO;
+        }
+        let most = self.config.link_latency;
+        let rng = &mut self.rng;
+        *self
+            .link_latencies
+            .entry((from, to))
+            .or_insert_with(|| rng.gen_range(Duration::ZERO..=most))
+    }
+
+    // Standard normal sample using the Box-Muller transform
+    fn gaussian(&mut self) -> f64 {
+        let u1: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        let u2: f64 = self.rng.gen();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    // Every packet gets through, and only the given delays are added
+    fn lossless() -> RadioConfig {
+        RadioConfig {
+            seed: 7,
+            shadowing_db: 0.0,
+            sensitivity_dbm: -1000.0,
+            latency: Duration::ZERO,
+            link_latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            bandwidth_bps: 0,
+            ..RadioConfig::default()
+        }
+    }
+
+    fn drone(id: usize, x: f32) -> DroneData {
+        DroneData::new(id, x, 0.0)
+    }
+
+    // Sends one packet at `now` and returns how long it took to arrive
+    fn delay(radio: &mut RadioModel, now: Duration, to: &DroneData, size: usize) -> Duration {
+        radio.transmit(
+            now,
+            &drone(0, 0.0),
+            std::slice::from_ref(to),
+            &vec![0; size],
+        );
+        let arrival = radio.next_arrival().unwrap();
+        assert_eq!(radio.arrived(arrival).len(), 1);
+        arrival - now
+    }
+
+    #[test]
+    fn loss_rises_with_distance() {
+        let mut radio = RadioModel::new(RadioConfig {
+            seed: 1,
+            ..RadioConfig::default()
+        });
+        let delivered: Vec<usize> = [50.0, 150.0, 300.0]
+            .iter()
+            .map(|distance| {
+                for _ in 0..1000 {
+                    radio.transmit(Duration::ZERO, &drone(0, 0.0), &[drone(1, *distance)], &[0]);
+                }
+                radio.arrived(Duration::MAX).len()
+            })
+            .collect();
+        assert!(delivered[0] > 990, "{:?}", delivered);
+        assert!((350..650).contains(&delivered[1]), "{:?}", delivered);
+        assert!(delivered[2] < 50, "{:?}", delivered);
+        assert_eq!(radio.stats().sent, 3000);
+        assert_eq!(
+            radio.stats().delivered + radio.stats().lost,
+            radio.stats().sent
+        );
+    }
+
+    #[test]
+    fn arrival_includes_every_delay() {
+        let mut radio = RadioModel::new(RadioConfig {
+            latency: 2 * MS,
+            link_latency: 3 * MS,
+            jitter: MS,
+            // 100 bytes take 100 ms
+            bandwidth_bps: 8000,
+            ..lossless()
+        });
+        let mut delays = Vec::new();
+        for i in 0..20 {
+            let now = Duration::from_secs(i);
+            delays.push(delay(&mut radio, now, &drone(1, 10.0), 100));
+        }
+        for delay in &delays {
+            assert!(*delay >= 102 * MS && *delay <= 106 * MS, "{:?}", delay);
+        }
+        // The link latency is drawn once, so only the jitter differs between packets
+        let spread = *delays.iter().max().unwrap() - *delays.iter().min().unwrap();
+        assert!(spread > Duration::ZERO && spread <= MS, "{:?}", spread);
+    }
+
+    #[test]
+    fn links_get_their_own_latency() {
+        let mut radio = RadioModel::new(RadioConfig {
+            link_latency: 3 * MS,
+            ..lossless()
+        });
+        let delays: Vec<Duration> = (1..=5)
+            .map(|id| delay(&mut radio, Duration::ZERO, &drone(id, 10.0), 1))
+            .collect();
+        for (id, first) in (1..=5).zip(&delays) {
+            assert!(*first <= 3 * MS);
+            assert_eq!(
+                delay(&mut radio, Duration::from_secs(1), &drone(id, 10.0), 1),
+                *first
+            );
+        }
+        assert!(
+            delays.iter().any(|delay| *delay != delays[0]),
+            "{:?}",
+            delays
+        );
+    }
+
+    #[test]
+    fn zero_jitter_gives_the_exact_delay() {
+        let mut radio = RadioModel::new(RadioConfig {
+            latency: 2 * MS,
+            bandwidth_bps: 8000,
+            ..lossless()
+        });
+        for i in 0..5 {
+            assert_eq!(
+                delay(&mut radio, Duration::from_secs(i), &drone(1, 10.0), 10),
+                12 * MS
+            );
+        }
+    }
+
+    #[test]
+    fn zero_bandwidth_is_no_limit() {
+        let mut radio = RadioModel::new(RadioConfig {
+            latency: 2 * MS,
+            ..lossless()
+        });
+        assert_eq!(
+            delay(&mut radio, Duration::ZERO, &drone(1, 10.0), 1000),
+            2 * MS
+        );
+    }
+
+    #[test]
+    fn unlimited_bandwidth_takes_no_time() {
+        let mut radio = RadioModel::new(RadioConfig {
+            latency: 2 * MS,
+            bandwidth_bps: u64::MAX,
+            ..lossless()
+        });
+        assert_eq!(
+            delay(&mut radio, Duration::ZERO, &drone(1, 10.0), 1000),
+            2 * MS
+        );
+    }
+
+    #[test]
+    fn transmitter_sends_one_packet_at_a_time() {
+        let mut radio = RadioModel::new(RadioConfig {
+            bandwidth_bps: 8000,
+            ..lossless()
+        });
+        // Each packet of 10 bytes takes 10 ms, and the second waits for the first
+        radio.transmit(Duration::ZERO, &drone(0, 0.0), &[drone(1, 10.0)], &[0; 10]);
+        radio.transmit(Duration::ZERO, &drone(0, 0.0), &[drone(1, 10.0)], &[1; 10]);
+        assert_eq!(radio.arrived(10 * MS), vec![(1, vec![0; 10])]);
+        assert_eq!(radio.next_arrival(), Some(20 * MS));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_run() {
+        let run = || {
+            let mut radio = RadioModel::new(RadioConfig::default());
+            for i in 0..100 {
+                radio.transmit(
+                    Duration::from_millis(i),
+                    &drone(0, 0.0),
+                    &[drone(1, 120.0), drone(2, 160.0)],
+                    &[i as u8],
+                );
+            }
+            radio.arrived(Duration::MAX)
+        };
+        assert_eq!(run(), run());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::radio::RadioConfig;
+use crate::scenario::{DroneSpec, Scenario};
+use drone::DroneConfig;
+use mesh_proto::{Coordinate, Mission};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+// Everything from outside the drones that can change how a run goes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "input", rename_all = "snake_case")]
+pub enum Input {
+    // Every drone on a mission flies one step of it, the others move one step towards
+    // the target if there is one
+    Step { target: Option<Coordinate> },
+    MoveDrone { id: usize, target: Coordinate },
+    AddDrone { drone: DroneSpec },
+    RemoveDrone { id: usize },
+    KillDrone { id: usize },
+    SetTargets { targets: Vec<Coordinate> },
+    // A search is recorded as the mission each drone got
+    StartMission { id: usize, mission: Mission },
+    CancelMission,
+    Reset,
+}
+
+// A recording is a JSON Lines file: a header, the inputs in the order they happened, and
+// for runs on the engine the outcome they led to
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+enum Entry {
+    Header {
+        scenario: Box<Scenario>,
+        drone_config: DroneConfig,
+        radio: RadioConfig,
+    },
+    Input(Input),
+    Outcome(Outcome),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Outcome {
+    pub ticks: u64,
+    pub finished: bool,
+    pub digest: String,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(PathBuf, io::Error),
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        error: serde_json::Error,
+    },
+    MissingHeader(PathBuf),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordingError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            RecordingError::Invalid { path, line, error } => {
+                write!(f, "{}:{}: {}", path.display(), line, error)
+            }
+            RecordingError::MissingHeader(path) => {
+                write!(
+                    f,
+                    "{}: the recording does not start with a header",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl Error for RecordingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RecordingError::Io(_, e) => Some(e),
+            RecordingError::Invalid { error, .. } => Some(error),
+            RecordingError::MissingHeader(_) => None,
+        }
+    }
+}
+
+// Appends inputs to a recording as they happen. Every line is flushed, so a run that is
+// killed can still be replayed up to that point.
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(
+        path: impl AsRef<Path>,
+        scenario: &Scenario,
+        drone_config: &DroneConfig,
+        radio: &RadioConfig,
+    ) -> io::Result<Self> {
+        let mut recorder = Recorder {
+            file: BufWriter::new(File::create(path)?),
+        };
+        recorder.write(&Entry::Header {
+            scenario: Box::new(scenario.clone()),
+            drone_config: drone_config.clone(),
+            radio: radio.clone(),
+        })?;
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, input: &Input) -> io::Result<()> {
+        self.write(&Entry::Input(input.clone()))
+    }
+
+    pub fn finish(&mut self, outcome: &Outcome) -> io::Result<()> {
+        self.write(&Entry::Outcome(outcome.clone()))
+    }
+
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        serde_json::to_writer(&mut self.file, entry)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+pub struct Recording {
+    pub scenario: Scenario,
+    pub drone_config: DroneConfig,
+    pub radio: RadioConfig,
+    pub inputs: Vec<Input>,
+    // Only runs on the engine are deterministic enough to have an outcome to compare with
+    pub outcome: Option<Outcome>,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording, RecordingError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| RecordingError::Io(path.into(), e))?;
+
+        let mut header = None;
+        let mut inputs = Vec::new();
+        let mut outcome = None;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| RecordingError::Io(path.into(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).map_err(|error| RecordingError::Invalid {
+                path: path.into(),
+                line: index + 1,
+                error,
+            })?;
+            match entry {
+                Entry::Header {
+                    scenario,
+                    drone_config,
+                    radio,
+                } => header = Some((scenario, drone_config, radio)),
+                Entry::Input(input) => inputs.push(input),
+                Entry::Outcome(recorded) => outcome = Some(recorded),
+            }
+        }
+
+        let Some((scenario, drone_config, radio)) = header else {
+            return Err(RecordingError::MissingHeader(path.into()));
+        };
+        Ok(Recording {
+            scenario: *scenario,
+            drone_config,
+            radio,
+            inputs,
+            outcome,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::{default_scenario, radio, run};
+    use crate::engine::Engine;
+    use std::fs;
+
+    #[test]
+    fn recording_replays_to_its_outcome() {
+        let scenario = default_scenario();
+        let path =
+            std::env::temp_dir().join(format!("aodv-recording-{}.jsonl", std::process::id()));
+        let mut recorder =
+            Recorder::create(&path, &scenario, &DroneConfig::default(), &radio(&scenario)).unwrap();
+        let engine = run(&scenario, |input| recorder.record(input).unwrap());
+        recorder.finish(&engine.outcome()).unwrap();
+
+        let recording = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!recording.inputs.is_empty());
+        assert_eq!(recording.outcome, Some(engine.outcome()));
+        assert_eq!(Engine::replay(&recording).outcome(), engine.outcome());
+    }
+}
@@ -0,0 +1,874 @@
+use drone::DroneConfig;
+use mesh_proto::addressing::MAX_DRONES;
+use mesh_proto::geometry::bounds;
+use mesh_proto::{Coordinate, DroneData, EncodeError, Mission, Packet, MAX_PACKET_SIZE};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+const LEGACY_DRONES_FILE: &str = "droner.txt";
+const LEGACY_LINKS_FILE: &str = "kobling_droner.txt";
+// Rows, wedges or spiral legs of a search pattern across the width of its area. The
+// drones work out every waypoint up front, so the spacing is bounded by the area.
+const MAX_PASSES: f32 = 1000.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub simulation: SimulationParams,
+    #[serde(default)]
+    pub search_area: Option<Area>,
+    #[serde(default)]
+    pub base_station: Option<BaseStation>,
+    pub drones: Vec<DroneSpec>,
+    #[serde(default)]
+    pub targets: Vec<Target>,
+    // Hidden from the drones and the frontend, the drones have to find them with their
+    // thermal sensors
+    #[serde(default)]
+    pub missing_persons: Vec<Target>,
+    #[serde(default)]
+    pub obstacles: Vec<Area>,
+    // Pairs of drones that start out as neighbors. Without it every pair of drones
+    // within radio range of each other is linked.
+    #[serde(default)]
+    pub links: Option<Vec<(usize, usize)>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimulationParams {
+    #[serde(default = "default_tick_ms")]
+    pub tick_ms: u64,
+    #[serde(default = "default_max_ticks")]
+    pub max_ticks: u64,
+    // Seed for the headless radio model
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl Default for SimulationParams {
+    fn default() -> Self {
+        SimulationParams {
+            tick_ms: default_tick_ms(),
+            max_ticks: default_max_ticks(),
+            seed: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DroneSpec {
+    pub id: usize,
+    pub position: Coordinate,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default = "default_radio_range")]
+    pub radio_range: f32,
+    // Remaining battery in percent
+    #[serde(default = "default_battery")]
+    pub battery: f32,
+}
+
+impl DroneSpec {
+    // A drone with the default speed, radio range and a full battery
+    pub fn new(id: usize, position: Coordinate) -> Self {
+        DroneSpec {
+            id,
+            position,
+            speed: default_speed(),
+            radio_range: default_radio_range(),
+            battery: default_battery(),
+        }
+    }
+
+    pub fn data(&self) -> DroneData {
+        DroneData::new(self.id, self.position.x, self.position.y)
+    }
+
+    // The drone settings that come from the scenario, on top of the base config
+    pub fn config(&self, base: &DroneConfig) -> DroneConfig {
+        DroneConfig {
+            speed: self.speed,
+            communication_radius: self.radio_range,
+            ..base.clone()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id >= MAX_DRONES {
+            return Err(format!(
+                "drone {}: ids must be below {}",
+                self.id, MAX_DRONES
+            ));
+        }
+        check_position(&self.position, &format!("drone {}", self.id))?;
+        if !(self.speed > 0.0 && self.speed.is_finite()) {
+            return Err(format!("drone {}: speed must be greater than 0", self.id));
+        }
+        if !(self.radio_range > 0.0 && self.radio_range.is_finite()) {
+            return Err(format!(
+                "drone {}: radio_range must be greater than 0",
+                self.id
+            ));
+        }
+        if !(0.0..=100.0).contains(&self.battery) {
+            return Err(format!(
+                "drone {}: battery must be between 0 and 100",
+                self.id
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn in_range_of(&self, other: &DroneSpec) -> bool {
+        let distance = ((self.position.x - other.position.x).powi(2)
+            + (self.position.y - other.position.y).powi(2))
+        .sqrt();
+        distance <= self.radio_range.min(other.radio_range)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    pub position: Coordinate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Area {
+    pub polygon: Vec<Coordinate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BaseStation {
+    // The base station is a node in the mesh, so it needs an id no drone uses
+    #[serde(default = "default_base_station_id")]
+    pub id: usize,
+    pub position: Coordinate,
+}
+
+fn default_base_station_id() -> usize {
+    MAX_DRONES - 1
+}
+
+fn default_tick_ms() -> u64 {
+    100
+}
+
+fn default_max_ticks() -> u64 {
+    10_000
+}
+
+fn default_speed() -> f32 {
+    DroneConfig::default().speed
+}
+
+fn default_radio_range() -> f32 {
+    DroneConfig::default().communication_radius
+}
+
+fn default_battery() -> f32 {
+    100.0
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(PathBuf, io::Error),
+    Toml(PathBuf, toml::de::Error),
+    Json(PathBuf, serde_json::Error),
+    UnknownFormat(PathBuf),
+    // Line and column are counted from 1
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScenarioError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ScenarioError::Toml(path, e) => write!(f, "{}: {}", path.display(), e),
+            ScenarioError::Json(path, e) => write!(f, "{}: {}", path.display(), e),
+            ScenarioError::UnknownFormat(path) => write!(
+                f,
+                "{}: scenario files must end in .toml or .json",
+                path.display()
+            ),
+            ScenarioError::Syntax {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+            ScenarioError::Invalid(path, reason) => write!(f, "{}: {}", path.display(), reason),
+        }
+    }
+}
+
+impl Error for ScenarioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ScenarioError::Io(_, e) => Some(e),
+            ScenarioError::Toml(_, e) => Some(e),
+            ScenarioError::Json(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> Result<Scenario, ScenarioError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| ScenarioError::Io(path.into(), e))?;
+        let scenario: Scenario = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => {
+                toml::from_str(&text).map_err(|e| ScenarioError::Toml(path.into(), e))?
+            }
+            Some("json") => {
+                serde_json::from_str(&text).map_err(|e| ScenarioError::Json(path.into(), e))?
+            }
+            _ => return Err(ScenarioError::UnknownFormat(path.into())),
+        };
+        scenario
+            .validate()
+            .map_err(|reason| ScenarioError::Invalid(path.into(), reason))?;
+        Ok(scenario)
+    }
+
+    // Reads the drones and the links between them from droner.txt and kobling_droner.txt
+    pub fn from_legacy_files() -> Result<Scenario, ScenarioError> {
+        let drones_path = Path::new(LEGACY_DRONES_FILE);
+        let text = fs::read_to_string(drones_path)
+            .map_err(|e| ScenarioError::Io(drones_path.into(), e))?;
+        let drones = parse_legacy_drones(drones_path, &text)?;
+
+        let links_path = Path::new(LEGACY_LINKS_FILE);
+        let text =
+            fs::read_to_string(links_path).map_err(|e| ScenarioError::Io(links_path.into(), e))?;
+        let links = parse_legacy_links(links_path, &text, &drones)?;
+
+        let scenario = Scenario {
+            simulation: SimulationParams::default(),
+            search_area: None,
+            base_station: None,
+            drones,
+            targets: Vec::new(),
+            missing_persons: Vec::new(),
+            obstacles: Vec::new(),
+            links: Some(links),
+        };
+        scenario
+            .validate()
+            .map_err(|reason| ScenarioError::Invalid(links_path.into(), reason))?;
+        Ok(scenario)
+    }
+
+    // The scenario as the frontend may see it, without the missing persons
+    pub fn public(&self) -> Scenario {
+        Scenario {
+            missing_persons: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    // The drones and the base station, which runs as a drone that does not fly
+    pub fn nodes(&self) -> Vec<DroneSpec> {
+        let mut nodes = self.drones.clone();
+        if let Some(base_station) = &self.base_station {
+            nodes.push(DroneSpec::new(
+                base_station.id,
+                base_station.position.clone(),
+            ));
+        }
+        nodes
+    }
+
+    // Parts of the search area with nothing to search
+    pub fn obstacle_polygons(&self) -> Vec<Vec<Coordinate>> {
+        self.obstacles
+            .iter()
+            .map(|obstacle| obstacle.polygon.clone())
+            .collect()
+    }
+
+    pub fn base_station_id(&self) -> Option<usize> {
+        self.base_station
+            .as_ref()
+            .map(|base_station| base_station.id)
+    }
+
+    pub fn drone_data(&self) -> Vec<DroneData> {
+        self.nodes().iter().map(DroneSpec::data).collect()
+    }
+
+    // Every link as the drone that is told about it and the neighbor it is told about
+    pub fn edges(&self) -> Vec<(usize, DroneData)> {
+        let links = match &self.links {
+            Some(links) => links.clone(),
+            None => self.links_in_range(),
+        };
+
+        let nodes = self.nodes();
+        let node = |id: usize| nodes.iter().find(|node| node.id == id);
+        let mut edges = Vec::new();
+        for (a, b) in links {
+            let (Some(a), Some(b)) = (node(a), node(b)) else {
+                continue;
+            };
+            edges.push((a.id, b.data()));
+            edges.push((b.id, a.data()));
+        }
+        edges
+    }
+
+    fn links_in_range(&self) -> Vec<(usize, usize)> {
+        let nodes = self.nodes();
+        let mut links = Vec::new();
+        for (i, a) in nodes.iter().enumerate() {
+            for b in &nodes[i + 1..] {
+                if a.in_range_of(b) {
+                    links.push((a.id, b.id));
+                }
+            }
+        }
+        links
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.simulation.tick_ms == 0 {
+            return Err("simulation.tick_ms must be greater than 0".to_string());
+        }
+        if self.drones.is_empty() {
+            return Err("at least one drone is needed".to_string());
+        }
+
+        let mut ids = BTreeSet::new();
+        for drone in &self.drones {
+            drone.validate()?;
+            if !ids.insert(drone.id) {
+                return Err(format!("drone {} is listed more than once", drone.id));
+            }
+        }
+
+        for (i, target) in self.targets.iter().enumerate() {
+            check_position(&target.position, &format!("target {}", i + 1))?;
+        }
+        for (i, person) in self.missing_persons.iter().enumerate() {
+            check_position(&person.position, &format!("missing person {}", i + 1))?;
+        }
+        if let Some(area) = &self.search_area {
+            check_polygon(&area.polygon, "search_area")?;
+        }
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            check_polygon(&obstacle.polygon, &format!("obstacle {}", i + 1))?;
+        }
+        if let Some(base_station) = &self.base_station {
+            check_position(&base_station.position, "base_station")?;
+            if base_station.id >= MAX_DRONES {
+                return Err(format!("base_station: id must be below {}", MAX_DRONES));
+            }
+            if !ids.insert(base_station.id) {
+                return Err(format!(
+                    "base_station: id {} is already used by a drone",
+                    base_station.id
+                ));
+            }
+        }
+
+        for (a, b) in self.links.iter().flatten() {
+            for id in [a, b] {
+                if !ids.contains(id) {
+                    return Err(format!(
+                        "link {}-{}: there is no drone or base station {}",
+                        a, b, id
+                    ));
+                }
+            }
+            if a == b {
+                return Err(format!("link {}-{}: a drone cannot link to itself", a, b));
+            }
+        }
+
+        let components = self.components();
+        if components.len() > 1 {
+            let groups: Vec<String> = components
+                .iter()
+                .map(|component| format!("{:?}", component))
+                .collect();
+            return Err(format!(
+                "the drones are split into groups that cannot reach each other: {}",
+                groups.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    // Groups of drones, and the base station, that are connected through the initial links
+    fn components(&self) -> Vec<BTreeSet<usize>> {
+        let mut neighbors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for node in self.nodes() {
+            neighbors.entry(node.id).or_default();
+        }
+        for (drone, neighbor) in self.edges() {
+            neighbors.entry(drone).or_default().push(neighbor.id);
+        }
+
+        let mut seen = BTreeSet::new();
+        let mut components = Vec::new();
+        for &start in neighbors.keys() {
+            if seen.contains(&start) {
+                continue;
+            }
+            let mut component = BTreeSet::new();
+            let mut stack = vec![start];
+            while let Some(id) = stack.pop() {
+                if !seen.insert(id) {
+                    continue;
+                }
+                component.insert(id);
+                stack.extend(neighbors[&id].iter().copied());
+            }
+            components.push(component);
+        }
+        components
+    }
+}
+
+fn check_position(position: &Coordinate, what: &str) -> Result<(), String> {
+    if position.x.is_finite() && position.y.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{}: position must be a finite coordinate", what))
+    }
+}
+
+// A mission has to be a real area, small enough to send to a drone in one packet, with
+// a spacing that does not give the drone millions of waypoints
+pub fn check_mission(mission: &Mission) -> Result<(), String> {
+    check_polygon(&mission.area, "mission area")?;
+    for (i, obstacle) in mission.obstacles.iter().enumerate() {
+        check_polygon(obstacle, &format!("mission obstacle {}", i + 1))?;
+    }
+    if !(mission.spacing.is_finite() && mission.spacing > 0.0) {
+        return Err("mission spacing must be positive".to_string());
+    }
+    let (min, max) = bounds(&mission.area);
+    let extent = (max.x - min.x).max(max.y - min.y);
+    if mission.spacing < extent / MAX_PASSES {
+        return Err(format!(
+            "mission spacing must be at least {} for this area",
+            extent / MAX_PASSES
+        ));
+    }
+    match Packet::Mission(mission.clone()).encode() {
+        Ok(_) => Ok(()),
+        Err(EncodeError::TooLarge(size)) => Err(format!(
+            "mission area has too many corners ({} bytes, at most {} fit in a packet)",
+            size, MAX_PACKET_SIZE
+        )),
+    }
+}
+
+fn check_polygon(polygon: &[Coordinate], what: &str) -> Result<(), String> {
+    if polygon.len() < 3 {
+        return Err(format!("{}: a polygon needs at least 3 corners", what));
+    }
+    for corner in polygon {
+        check_position(corner, what)?;
+    }
+    Ok(())
+}
+
+// Splits a line into its words and the column each starts at
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut column = 0;
+    for (offset, character) in line.char_indices() {
+        column += 1;
+        match (character.is_whitespace(), start) {
+            (false, None) => start = Some((column, offset)),
+            (true, Some((first, begin))) => {
+                words.push((first, &line[begin..offset]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((first, begin)) = start {
+        words.push((first, &line[begin..]));
+    }
+    words
+}
+
+fn syntax_error(path: &Path, line: usize, column: usize, message: String) -> ScenarioError {
+    ScenarioError::Syntax {
+        path: path.into(),
+        line,
+        column,
+        message,
+    }
+}
+
+fn parse_word<T: std::str::FromStr>(
+    path: &Path,
+    line: usize,
+    (column, word): (usize, &str),
+    what: &str,
+) -> Result<T, ScenarioError> {
+    word.parse().map_err(|_| {
+        syntax_error(
+            path,
+            line,
+            column,
+            format!("expected {}, found '{}'", what, word),
+        )
+    })
+}
+
+// Reads the words of a line, failing if there are more or fewer than expected
+fn expect_words<'a>(
+    path: &Path,
+    line: usize,
+    text: &'a str,
+    names: &[&str],
+) -> Result<Vec<(usize, &'a str)>, ScenarioError> {
+    let words = words(text);
+    if words.len() < names.len() {
+        let column = text.chars().count() + 1;
+        return Err(syntax_error(
+            path,
+            line,
+            column,
+            format!("missing {}", names[words.len()]),
+        ));
+    }
+    if let Some((column, word)) = words.get(names.len()) {
+        return Err(syntax_error(
+            path,
+            line,
+            *column,
+            format!("unexpected '{}' after {}", word, names[names.len() - 1]),
+        ));
+    }
+    Ok(words)
+}
+
+// droner.txt has an optional header line followed by one "id x y" line per drone.
+// Ids have to run from 0 without gaps, since kobling_droner.txt counts the drones.
+fn parse_legacy_drones(path: &Path, text: &str) -> Result<Vec<DroneSpec>, ScenarioError> {
+    let mut drones: Vec<DroneSpec> = Vec::new();
+    let mut lines_by_id = BTreeMap::new();
+    let mut first_line = true;
+
+    for (index, text) in text.lines().enumerate() {
+        let line = index + 1;
+        let Some(&(_, first)) = words(text).first() else {
+            continue;
+        };
+        // The first line may be a header like "id x y"
+        let header = first_line && first.parse::<usize>().is_err();
+        first_line = false;
+        if header {
+            continue;
+        }
+
+        let words = expect_words(path, line, text, &["id", "x coordinate", "y coordinate"])?;
+        let id: usize = parse_word(path, line, words[0], "a drone id")?;
+        let x: f32 = parse_word(path, line, words[1], "an x coordinate")?;
+        let y: f32 = parse_word(path, line, words[2], "a y coordinate")?;
+
+        if let Some(first_line) = lines_by_id.insert(id, line) {
+            return Err(syntax_error(
+                path,
+                line,
+                words[0].0,
+                format!("drone {} is already listed on line {}", id, first_line),
+            ));
+        }
+        drones.push(DroneSpec::new(id, Coordinate { x, y }));
+    }
+
+    if drones.is_empty() {
+        return Err(ScenarioError::Invalid(
+            path.into(),
+            "no drones are listed".to_string(),
+        ));
+    }
+    for expected in 0..drones.len() {
+        if !lines_by_id.contains_key(&expected) {
+            return Err(ScenarioError::Invalid(
+                path.into(),
+                format!(
+                    "drone ids must run from 0 to {} without gaps, drone {} is missing",
+                    drones.len() - 1,
+                    expected
+                ),
+            ));
+        }
+    }
+    Ok(drones)
+}
+
+// kobling_droner.txt starts with "drones links" followed by one "from to" line per link
+fn parse_legacy_links(
+    path: &Path,
+    text: &str,
+    drones: &[DroneSpec],
+) -> Result<Vec<(usize, usize)>, ScenarioError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, text)| (index + 1, text))
+        .filter(|(_, text)| !text.trim().is_empty());
+
+    let Some((line, header)) = lines.next() else {
+        return Err(ScenarioError::Invalid(
+            path.into(),
+            "the file is empty, expected the number of drones and links".to_string(),
+        ));
+    };
+    let words = expect_words(path, line, header, &["number of drones", "number of links"])?;
+    let declared_drones: usize = parse_word(path, line, words[0], "the number of drones")?;
+    let declared_links: usize = parse_word(path, line, words[1], "the number of links")?;
+    if declared_drones != drones.len() {
+        return Err(syntax_error(
+            path,
+            line,
+            words[0].0,
+            format!(
+                "{} drones declared, but {} has {}",
+                declared_drones,
+                LEGACY_DRONES_FILE,
+                drones.len()
+            ),
+        ));
+    }
+
+    let mut links = BTreeSet::new();
+    let mut count = 0;
+    let mut last_line = line;
+    for (line, text) in lines {
+        last_line = line;
+        count += 1;
+        let words = expect_words(path, line, text, &["drone id", "neighbor id"])?;
+        let from: usize = parse_word(path, line, words[0], "a drone id")?;
+        let to: usize = parse_word(path, line, words[1], "a drone id")?;
+        for (column, id) in [(words[0].0, from), (words[1].0, to)] {
+            if id >= drones.len() {
+                return Err(syntax_error(
+                    path,
+                    line,
+                    column,
+                    format!("there is no drone {}", id),
+                ));
+            }
+        }
+        if from == to {
+            return Err(syntax_error(
+                path,
+                line,
+                words[1].0,
+                format!("drone {} cannot link to itself", from),
+            ));
+        }
+        links.insert((from.min(to), from.max(to)));
+    }
+
+    if count != declared_links {
+        return Err(syntax_error(
+            path,
+            last_line,
+            1,
+            format!(
+                "{} links declared, but {} are listed",
+                declared_links, count
+            ),
+        ));
+    }
+    Ok(links.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three drones in a row, each in range of the next
+    const DRONES: &str = "id x y\n0 0 0\n1 100 0\n2 200.5 0\n";
+
+    fn drones() -> Vec<DroneSpec> {
+        parse_legacy_drones(Path::new(LEGACY_DRONES_FILE), DRONES).unwrap()
+    }
+
+    fn drones_error(text: &str) -> ScenarioError {
+        parse_legacy_drones(Path::new(LEGACY_DRONES_FILE), text).unwrap_err()
+    }
+
+    fn links_error(text: &str) -> ScenarioError {
+        parse_legacy_links(Path::new(LEGACY_LINKS_FILE), text, &drones()).unwrap_err()
+    }
+
+    fn assert_syntax(error: ScenarioError, expected: (usize, usize, &str)) {
+        match error {
+            ScenarioError::Syntax {
+                line,
+                column,
+                message,
+                ..
+            } => assert_eq!((line, column, message.as_str()), expected),
+            other => panic!("expected a syntax error, got {}", other),
+        }
+    }
+
+    fn assert_invalid(error: ScenarioError, expected: &str) {
+        match error {
+            ScenarioError::Invalid(_, reason) => assert_eq!(reason, expected),
+            other => panic!("expected an invalid scenario, got {}", other),
+        }
+    }
+
+    fn scenario(links: Vec<(usize, usize)>) -> Scenario {
+        Scenario {
+            simulation: SimulationParams::default(),
+            search_area: None,
+            base_station: None,
+            drones: drones(),
+            targets: Vec::new(),
+            missing_persons: Vec::new(),
+            obstacles: Vec::new(),
+            links: Some(links),
+        }
+    }
+
+    #[test]
+    fn legacy_drones_are_read_with_fractional_positions() {
+        let drones = drones();
+        assert_eq!(drones.len(), 3);
+        assert_eq!(drones[2].id, 2);
+        assert_eq!(drones[2].position, Coordinate { x: 200.5, y: 0.0 });
+    }
+
+    #[test]
+    fn bad_coordinate_is_reported_where_it_is() {
+        assert_syntax(
+            drones_error("0 0 0\n1 x 0\n"),
+            (2, 3, "expected an x coordinate, found 'x'"),
+        );
+    }
+
+    #[test]
+    fn missing_and_extra_words_are_reported() {
+        assert_syntax(drones_error("0 0 0\n1 5\n"), (2, 4, "missing y coordinate"));
+        assert_syntax(
+            drones_error("0 0 0 7\n"),
+            (1, 7, "unexpected '7' after y coordinate"),
+        );
+    }
+
+    #[test]
+    fn duplicate_drone_is_rejected() {
+        assert_syntax(
+            drones_error("0 0 0\n1 5 5\n  1 6 6\n"),
+            (3, 3, "drone 1 is already listed on line 2"),
+        );
+    }
+
+    #[test]
+    fn drone_ids_must_not_have_gaps() {
+        assert_invalid(
+            drones_error("0 0 0\n2 5 5\n"),
+            "drone ids must run from 0 to 1 without gaps, drone 1 is missing",
+        );
+        assert_invalid(drones_error("id x y\n\n"), "no drones are listed");
+    }
+
+    #[test]
+    fn legacy_links_are_read() {
+        let links =
+            parse_legacy_links(Path::new(LEGACY_LINKS_FILE), "3 2\n1 0\n1 2\n", &drones()).unwrap();
+        assert_eq!(links, vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn link_to_unknown_drone_is_rejected() {
+        assert_syntax(
+            links_error("3 2\n0 1\n1 5\n"),
+            (3, 3, "there is no drone 5"),
+        );
+    }
+
+    #[test]
+    fn self_loop_is_rejected() {
+        assert_syntax(
+            links_error("3 1\n2 2\n"),
+            (2, 3, "drone 2 cannot link to itself"),
+        );
+    }
+
+    #[test]
+    fn declared_counts_must_match() {
+        assert_syntax(
+            links_error("4 1\n0 1\n"),
+            (1, 1, "4 drones declared, but droner.txt has 3"),
+        );
+        assert_syntax(
+            links_error("3 2\n0 1\n"),
+            (2, 1, "2 links declared, but 1 are listed"),
+        );
+        assert_syntax(
+            links_error("3 two\n"),
+            (1, 3, "expected the number of links, found 'two'"),
+        );
+    }
+
+    #[test]
+    fn disconnected_drones_are_rejected() {
+        let mut scenario = scenario(vec![(0, 1)]);
+        assert_eq!(
+            scenario.components(),
+            vec![BTreeSet::from([0, 1]), BTreeSet::from([2])]
+        );
+        assert_eq!(
+            scenario.validate().unwrap_err(),
+            "the drones are split into groups that cannot reach each other: {0, 1}, {2}"
+        );
+
+        scenario.links = Some(vec![(0, 1), (1, 2)]);
+        assert_eq!(scenario.components().len(), 1);
+        assert!(scenario.validate().is_ok());
+    }
+
+    #[test]
+    fn bad_links_and_ids_are_rejected() {
+        assert_eq!(
+            scenario(vec![(0, 1), (1, 7)]).validate().unwrap_err(),
+            "link 1-7: there is no drone or base station 7"
+        );
+        assert_eq!(
+            scenario(vec![(0, 1), (1, 2), (2, 2)])
+                .validate()
+                .unwrap_err(),
+            "link 2-2: a drone cannot link to itself"
+        );
+
+        let mut duplicate = scenario(vec![(0, 1), (1, 2)]);
+        duplicate.drones.push(duplicate.drones[0].clone());
+        assert_eq!(
+            duplicate.validate().unwrap_err(),
+            "drone 0 is listed more than once"
+        );
+    }
+}
@@ -1,283 +1,902 @@
-use serde::{Deserialize, Serialize};
+use crate::capture::{self, Capture};
+use crate::coverage::{Coverage, CoverageReport};
+use crate::events::{Event, Events};
+use crate::launcher::Launcher;
+use crate::partition::{Search, Searcher};
+use crate::persons::{DetectionReport, MissingPersons};
+use crate::radio::{RadioConfig, RadioModel};
+use crate::recording::{Input, Recorder};
+use crate::scenario::{check_mission, DroneSpec, Scenario};
+use drone::DroneConfig;
+use mesh_proto::addressing::{
+    command_address, drone_address, drone_id, relay_bind_address, simulator_bind_address,
+    MAX_DRONES,
+};
+use mesh_proto::{
+    relay, Coordinate, DroneData, Mission, Packet, RouteEntry, SearchReport, MAX_PACKET_SIZE,
+};
+use serde::Serialize;
 use std::{
-    env,
-    fs::File,
-    io::{BufRead, BufReader},
+    collections::BTreeMap,
+    error::Error,
+    fmt, io,
     net::UdpSocket,
-    path::PathBuf,
-    process::Command,
     sync::{Arc, Mutex},
     thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Coordinate {
-    pub x: f32,
-    pub y: f32,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct DroneData {
-    id: usize,
-    x: f32,
-    y: f32,
+const LOG_DIR: &str = "logs";
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// How long a removed drone gets to shut down before it is killed
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+// How long the relay waits for traffic when no packet is in flight
+const RELAY_IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum SimulatorError {
+    Io(io::Error),
+    UnknownDrone(usize),
+    DuplicateDrone(usize),
+    InvalidDrone(String),
+    NoTarget,
+    InvalidMission(String),
+    NoMission,
+    // The drones were started by hand, so the simulator cannot start or kill them
+    NotLaunched,
 }
 
-#[derive(Debug, Clone)]
-struct Graph {
-    drones: Vec<Vec<Drone>>,
+impl fmt::Display for SimulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulatorError::Io(e) => write!(f, "network error: {}", e),
+            SimulatorError::UnknownDrone(id) => write!(f, "there is no drone {}", id),
+            SimulatorError::DuplicateDrone(id) => write!(f, "drone {} already exists", id),
+            SimulatorError::InvalidDrone(reason) => write!(f, "{}", reason),
+            SimulatorError::NoTarget => write!(f, "no target is set"),
+            SimulatorError::InvalidMission(reason) => write!(f, "{}", reason),
+            SimulatorError::NoMission => write!(f, "no mission has been started"),
+            SimulatorError::NotLaunched => {
+                write!(f, "the drones were not started by the simulator")
+            }
+        }
+    }
 }
 
-impl Graph {
-    fn with_nodes(amount_of_nodes: usize) -> Self {
-        let mut drones: Vec<Vec<Drone>> = Vec::with_capacity(amount_of_nodes);
-        for _ in 0..amount_of_nodes {
-            drones.push(Vec::new());
+impl Error for SimulatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SimulatorError::Io(e) => Some(e),
+            _ => None,
         }
-        Self { drones }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Drone {
-    id: usize,
-    x: f32,
-    y: f32,
+impl From<io::Error> for SimulatorError {
+    fn from(e: io::Error) -> Self {
+        SimulatorError::Io(e)
+    }
 }
 
-impl Drone {
-    fn new(id: usize, x: f32, y: f32) -> Drone {
-        Drone { id, x, y }
-    }
+// The last routing table a drone reported
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingTableReport {
+    pub id: usize,
+    pub neighbors: Vec<usize>,
+    pub routes: Vec<RouteEntry>,
+    #[serde(skip)]
+    pub received: Instant,
 }
 
 #[derive(Clone)]
 pub struct Simulator {
-    graph: Graph,
-    pub drones: Arc<Mutex<Vec<Drone>>>,
-    finished: Arc<Mutex<bool>>,
+    // The scenario the simulation started from, used to reset it
+    scenario: Scenario,
+    specs: Arc<Mutex<Vec<DroneSpec>>>,
+    edges: Arc<Mutex<Vec<(usize, DroneData)>>>,
+    pub drones: Arc<Mutex<Vec<DroneData>>>,
+    targets: Arc<Mutex<Vec<Coordinate>>>,
+    routing_tables: Arc<Mutex<BTreeMap<usize, RoutingTableReport>>>,
+    // Neighbors each drone last reported
+    neighbors: Arc<Mutex<BTreeMap<usize, Vec<usize>>>>,
+    // The drone that reached the target
+    finished: Arc<Mutex<Option<usize>>>,
+    // The mission each drone is flying
+    missions: Arc<Mutex<BTreeMap<usize, Mission>>>,
+    // The area the whole swarm is searching, split again when drones join or leave
+    search: Arc<Mutex<Option<Search>>>,
+    // How much of the last mission area has been flown over
+    coverage: Arc<Mutex<Option<Coverage>>>,
+    persons: Arc<MissingPersons>,
+    detections: Arc<Mutex<Vec<DetectionReport>>>,
+    // Reports that have reached the base station
+    reports: Arc<Mutex<Vec<SearchReport>>>,
+    events: Events,
+    commands: Arc<UdpSocket>,
+    // Inputs are recorded so the run can be replayed on the engine
+    recorder: Option<Arc<Mutex<Recorder>>>,
+    launcher: Option<Arc<Mutex<Launcher>>>,
 }
 
 impl Simulator {
-    pub fn new() -> Self {
-        let drones_file_path = "droner.txt";
-        let lines = read_file(drones_file_path).unwrap();
-        let drones = make_drones(lines);
-
-        let connection_file_path = "kobling_droner.txt";
-        let lines = read_file(connection_file_path).unwrap();
-        let graph = make_graph(lines, drones.lock().unwrap().clone());
+    // With `launch` set the drone programs are started as child processes, otherwise
+    // they are expected to be started by hand. With a capture the launched drones copy
+    // every packet they send to the simulator, which records it. Traffic between the
+    // launched drones goes through the radio model.
+    pub fn new(
+        scenario: &Scenario,
+        launch: bool,
+        radio: RadioConfig,
+        capture: Option<Capture>,
+        recorder: Option<Recorder>,
+    ) -> io::Result<Self> {
+        let launcher = if launch {
+            let trace = capture.is_some();
+            let launcher = Launcher::new(LOG_DIR, trace, scenario.base_station_id())?;
+            Some(Arc::new(Mutex::new(launcher)))
+        } else {
+            None
+        };
+        let socket = UdpSocket::bind(simulator_bind_address())?;
+        let relay = UdpSocket::bind(relay_bind_address())?;
         let sim = Self {
-            graph,
-            drones,
-            finished: Arc::new(Mutex::new(false)),
+            scenario: scenario.clone(),
+            specs: Arc::new(Mutex::new(scenario.nodes())),
+            edges: Arc::new(Mutex::new(scenario.edges())),
+            drones: Arc::new(Mutex::new(scenario.drone_data())),
+            targets: Arc::new(Mutex::new(initial_targets(scenario))),
+            routing_tables: Arc::new(Mutex::new(BTreeMap::new())),
+            neighbors: Arc::new(Mutex::new(BTreeMap::new())),
+            finished: Arc::new(Mutex::new(None)),
+            missions: Arc::new(Mutex::new(BTreeMap::new())),
+            search: Arc::new(Mutex::new(None)),
+            coverage: Arc::new(Mutex::new(None)),
+            // The launched drones read the same sensor settings from the environment
+            persons: Arc::new(MissingPersons::new(scenario, &DroneConfig::from_env())),
+            detections: Arc::new(Mutex::new(Vec::new())),
+            reports: Arc::new(Mutex::new(Vec::new())),
+            events: Events::new(),
+            commands: Arc::new(UdpSocket::bind(command_address())?),
+            recorder: recorder.map(|recorder| Arc::new(Mutex::new(recorder))),
+            launcher,
         };
-        sim.start_reader_thread();
-        make_edges(&sim);
+        sim.start_reader_thread(socket, capture);
+        sim.start_relay_thread(relay, radio);
 
-        if env::consts::OS == "windows" {
+        if sim.launcher.is_some() {
             run_drones(&sim);
+            sim.start_health_check_thread();
         }
 
-        sim
+        Ok(sim)
     }
 
-    pub fn do_step(&self, x: f32, y: f32) {
-        let standard_port = 8080;
-        let url = "127.0.0.1:";
-        let message = "MOVE";
+    // Stops the launched drone programs
+    pub fn shutdown(&self) {
+        if let Some(launcher) = &self.launcher {
+            launcher.lock().unwrap().kill_all();
+        }
+    }
+
+    fn start_health_check_thread(&self) {
+        let Some(launcher) = self.launcher.clone() else {
+            return;
+        };
+
+        let sim = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(HEALTH_CHECK_INTERVAL);
+            let exited = launcher.lock().unwrap().check();
+            for id in exited {
+                println!("Drone {} is down, see {}/drone-{}.log", id, LOG_DIR, id);
+                // Gone like a killed drone, and its share of the search goes to the others
+                sim.forget_drone(id);
+                sim.record(Input::KillDrone { id });
+                sim.split_search_again();
+            }
+        });
+    }
+
+    // Moves every drone on a mission one step along it, and the others one step towards the
+    // target, or the first target set if none is given
+    pub fn do_step(&self, target: Option<Coordinate>) -> Result<(), SimulatorError> {
+        let target = target.or_else(|| self.targets.lock().unwrap().first().cloned());
+        let missions = self.missions.lock().unwrap().clone();
+        if target.is_none() && missions.is_empty() {
+            return Err(SimulatorError::NoTarget);
+        }
+        self.record(Input::Step {
+            target: target.clone(),
+        });
 
-        let socket = UdpSocket::bind("127.0.0.1:7879").expect("Could not bind socket");
         for drone in &*self.drones.lock().unwrap() {
             println!("Drone {}: x: {}, y: {}", drone.id, drone.x, drone.y);
-            let port = standard_port + drone.id;
-            socket
-                .send_to(
-                    format!("{} {} {}", message, x, y).as_bytes(),
-                    format!("{}{}", url, port),
-                )
-                .unwrap();
+            let message = if missions.contains_key(&drone.id) {
+                Packet::MissionStep
+            } else if let Some(target) = &target {
+                Packet::Move {
+                    target: target.clone(),
+                }
+            } else {
+                continue;
+            };
+            if let Err(e) = self.send_to_drone(&message, drone.id) {
+                println!("Could not send step to drone {}: {}", drone.id, e);
+            }
         }
         println!("Sent all the messages to the drones");
+        Ok(())
     }
 
-    fn start_reader_thread(&self) {
-        let drones_clone = self.drones.clone();
-        let finished_clone = self.finished.clone();
+    pub fn move_drone(&self, id: usize, target: Coordinate) -> Result<(), SimulatorError> {
+        self.check_known(id)?;
+        self.record(Input::MoveDrone {
+            id,
+            target: target.clone(),
+        });
+        self.send_to_drone(&Packet::Move { target }, id)?;
+        // The drone drops its mission when it is moved
+        self.missions.lock().unwrap().remove(&id);
+        Ok(())
+    }
 
-        thread::spawn(move || {
-            let socket = UdpSocket::bind("0.0.0.0:7878").expect("Could not bind socket");
-            loop {
-                let mut buffer = [0u8; 1024];
-                if let Ok((size, _)) = socket.recv_from(&mut buffer) {
-                    let message = std::str::from_utf8(&buffer[..size]).unwrap();
-                    if let Ok(parsed) = message.parse::<bool>() {
-                        if parsed {
-                            println!("Target reached");
-                            *finished_clone.lock().unwrap() = true;
-                            break;
-                        }
-                    }
-                    let data = Some(serde_json::from_str::<DroneData>(message).unwrap());
+    // Splits the area among the drones and sends each of them their share. Drones that
+    // join or leave later get the area split again.
+    pub fn start_search(&self, search: Search) -> Result<(), SimulatorError> {
+        check_mission(&search.mission()).map_err(SimulatorError::InvalidMission)?;
+        let assignments = search
+            .assign(&self.searchers())
+            .map_err(SimulatorError::InvalidMission)?;
+        *self.coverage.lock().unwrap() = Some(Coverage::new(
+            &search.area,
+            &search.obstacles,
+            search.spacing,
+        ));
+        *self.search.lock().unwrap() = Some(search.clone());
+        self.send_shares(&search, assignments)
+    }
 
-                    if let Some(data) = data {
-                        let dron_data: DroneData = data;
-                        let mut drones = drones_clone.lock().unwrap();
-                        for drone in &mut *drones {
-                            if drone.id == dron_data.id {
-                                drone.x = dron_data.x;
-                                drone.y = dron_data.y;
-                            }
-                        }
-                    }
-                }
+    // Gives one drone a mission of its own. It keeps it until the search area is split
+    // again.
+    pub fn start_mission(&self, id: usize, mission: Mission) -> Result<(), SimulatorError> {
+        check_mission(&mission).map_err(SimulatorError::InvalidMission)?;
+        self.check_known(id)?;
+        if self.search.lock().unwrap().is_none() {
+            let mut coverage = self.coverage.lock().unwrap();
+            if coverage
+                .as_ref()
+                .is_none_or(|coverage| coverage.area() != mission.area.as_slice())
+            {
+                *coverage = Some(Coverage::new(
+                    &mission.area,
+                    &mission.obstacles,
+                    mission.spacing,
+                ));
+            }
+        }
+        self.assign_mission(id, mission)?;
+        Ok(())
+    }
+
+    // Stops the search and every mission
+    pub fn cancel_mission(&self) {
+        self.record(Input::CancelMission);
+        *self.search.lock().unwrap() = None;
+        let missions = std::mem::take(&mut *self.missions.lock().unwrap());
+        for id in missions.keys() {
+            if let Err(e) = self.send_to_drone(&Packet::CancelMission, *id) {
+                println!("Could not cancel the mission of drone {}: {}", id, e);
             }
+        }
+    }
+
+    // Hands out the search area among the drones there are now. Nothing is sent when
+    // some share is too big for a packet.
+    fn partition_search(&self) -> Result<(), SimulatorError> {
+        let Some(search) = self.search.lock().unwrap().clone() else {
+            return Ok(());
+        };
+        let assignments = search
+            .assign(&self.searchers())
+            .map_err(SimulatorError::InvalidMission)?;
+        self.send_shares(&search, assignments)
+    }
+
+    // A drone that gets no share stops searching
+    fn send_shares(
+        &self,
+        search: &Search,
+        assignments: BTreeMap<usize, Mission>,
+    ) -> Result<(), SimulatorError> {
+        let unassigned: Vec<usize> = self
+            .missions
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|id| !assignments.contains_key(id))
+            .copied()
+            .collect();
+        for id in unassigned {
+            self.missions.lock().unwrap().remove(&id);
+            self.send_to_drone(&Packet::CancelMission, id)?;
+        }
+
+        println!(
+            "Split the search area among {} drones by {:?}",
+            assignments.len(),
+            search.partitioning
+        );
+        for (id, mission) in assignments {
+            self.assign_mission(id, mission)?;
+        }
+        Ok(())
+    }
+
+    // For drones joining or leaving, which should not fail because of the search
+    fn split_search_again(&self) {
+        if let Err(e) = self.partition_search() {
+            println!("Could not split the search area again: {}", e);
+        }
+    }
+
+    fn searchers(&self) -> Vec<Searcher> {
+        let specs = self.specs.lock().unwrap();
+        let base_station = self.scenario.base_station_id();
+        self.get_drones()
+            .into_iter()
+            .filter(|drone| Some(drone.id) != base_station)
+            .filter_map(|drone| {
+                let spec = specs.iter().find(|spec| spec.id == drone.id)?;
+                Some(Searcher {
+                    id: drone.id,
+                    position: Coordinate {
+                        x: drone.x,
+                        y: drone.y,
+                    },
+                    speed: spec.speed,
+                    battery: spec.battery,
+                })
+            })
+            .collect()
+    }
+
+    fn assign_mission(&self, id: usize, mission: Mission) -> io::Result<()> {
+        self.record(Input::StartMission {
+            id,
+            mission: mission.clone(),
         });
+        self.send_to_drone(&Packet::Mission(mission.clone()), id)?;
+        println!("Sent {:?} mission to drone {}", mission.pattern, id);
+        self.missions.lock().unwrap().insert(id, mission.clone());
+        self.events.publish(Event::Mission { id, mission });
+        Ok(())
     }
 
-    pub fn get_drones(&self) -> Vec<Drone> {
-        self.drones.lock().unwrap().clone()
+    pub fn reports(&self) -> Vec<SearchReport> {
+        self.reports.lock().unwrap().clone()
     }
 
-    pub fn update_drones(&self) {
-        let standard_port = 8080;
-        let socket = UdpSocket::bind("127.0.0.1:7879").expect("Could not bind socket");
+    pub fn detections(&self) -> Vec<DetectionReport> {
+        self.detections.lock().unwrap().clone()
+    }
 
-        for drone in &*self.drones.lock().unwrap() {
-            let port = standard_port + drone.id;
-            socket
-                .send_to("POSITION".as_bytes(), format!("127.0.0.1:{}", port))
-                .unwrap();
+    pub fn missions(&self) -> BTreeMap<usize, Mission> {
+        self.missions.lock().unwrap().clone()
+    }
+
+    pub fn coverage(&self) -> Result<CoverageReport, SimulatorError> {
+        self.coverage
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(Coverage::report)
+            .ok_or(SimulatorError::NoMission)
+    }
+
+    // Adds a drone to the running simulation. It starts out linked to the drones within
+    // radio range of where they are now, and is launched if the simulator launches drones.
+    pub fn add_drone(&self, spec: DroneSpec) -> Result<DroneSpec, SimulatorError> {
+        spec.validate().map_err(SimulatorError::InvalidDrone)?;
+
+        let mut specs = self.specs.lock().unwrap();
+        if specs.iter().any(|drone| drone.id == spec.id) {
+            return Err(SimulatorError::DuplicateDrone(spec.id));
+        }
+
+        let mut drones = self.drones.lock().unwrap();
+        let mut edges = self.edges.lock().unwrap();
+        for other in specs.iter() {
+            let Some(current) = drones.iter().find(|drone| drone.id == other.id) else {
+                continue;
+            };
+            let moved = DroneSpec {
+                position: Coordinate {
+                    x: current.x,
+                    y: current.y,
+                },
+                ..other.clone()
+            };
+            if spec.in_range_of(&moved) {
+                edges.push((spec.id, current.clone()));
+                edges.push((other.id, spec.data()));
+            }
+        }
+        specs.push(spec.clone());
+        drones.push(spec.data());
+        self.events.publish(Event::DroneAdded(spec.data()));
+        self.record(Input::AddDrone {
+            drone: spec.clone(),
+        });
+        println!("Added drone {}", spec.id);
+
+        if let Some(launcher) = &self.launcher {
+            launcher.lock().unwrap().spawn(&spec)?;
         }
+        Ok(spec)
     }
 
-    pub fn is_finished(&self) -> bool {
-        *self.finished.lock().unwrap()
+    // The lowest id no drone is using
+    pub fn free_id(&self) -> Result<usize, SimulatorError> {
+        let specs = self.specs.lock().unwrap();
+        (0..MAX_DRONES)
+            .find(|id| specs.iter().all(|drone| drone.id != *id))
+            .ok_or_else(|| {
+                SimulatorError::InvalidDrone(format!("all {} drone ids are in use", MAX_DRONES))
+            })
     }
-}
 
-fn read_file(path: &str) -> Option<Vec<String>> {
-    let file = File::open(path);
+    // Tells the drone to leave the swarm and stops following it
+    pub fn remove_drone(&self, id: usize) -> Result<(), SimulatorError> {
+        self.check_known(id)?;
+        if let Err(e) = self.send_to_drone(&Packet::Shutdown, id) {
+            println!("Could not tell drone {} to shut down: {}", id, e);
+        }
+        self.forget_drone(id);
+        self.record(Input::RemoveDrone { id });
+        if let Some(launcher) = &self.launcher {
+            launcher.lock().unwrap().stop(id, SHUTDOWN_GRACE);
+        }
+        println!("Removed drone {}", id);
+        self.split_search_again();
+        Ok(())
+    }
 
-    // Handle any potential errors when opening the file
-    let file = match file {
-        Ok(f) => f,
-        Err(e) => {
-            println!("Error opening the file: {}", e);
-            return None;
+    // Kills the drone program without warning, so its neighbors have to notice it is gone
+    pub fn kill_drone(&self, id: usize) -> Result<(), SimulatorError> {
+        self.check_known(id)?;
+        let Some(launcher) = &self.launcher else {
+            return Err(SimulatorError::NotLaunched);
+        };
+        if !launcher.lock().unwrap().kill(id) {
+            return Err(SimulatorError::NotLaunched);
         }
-    };
+        self.forget_drone(id);
+        self.record(Input::KillDrone { id });
+        println!("Killed drone {}", id);
+        self.split_search_again();
+        Ok(())
+    }
+
+    pub fn targets(&self) -> Vec<Coordinate> {
+        self.targets.lock().unwrap().clone()
+    }
 
-    let reader = BufReader::new(file);
+    // New targets start a new search
+    pub fn set_targets(&self, targets: Vec<Coordinate>) {
+        self.record(Input::SetTargets {
+            targets: targets.clone(),
+        });
+        *self.targets.lock().unwrap() = targets;
+        *self.finished.lock().unwrap() = None;
+    }
 
-    let lines: Vec<String> = reader
-        .lines()
-        .into_iter()
-        .map(|line| line.expect("Failed to read line"))
-        .collect();
+    // Stops every drone and starts the scenario over from the beginning
+    pub fn reset(&self) -> Result<(), SimulatorError> {
+        let Some(launcher) = &self.launcher else {
+            return Err(SimulatorError::NotLaunched);
+        };
+        launcher.lock().unwrap().kill_all();
+
+        *self.specs.lock().unwrap() = self.scenario.nodes();
+        *self.edges.lock().unwrap() = self.scenario.edges();
+        *self.drones.lock().unwrap() = self.scenario.drone_data();
+        *self.targets.lock().unwrap() = initial_targets(&self.scenario);
+        self.routing_tables.lock().unwrap().clear();
+        self.neighbors.lock().unwrap().clear();
+        *self.finished.lock().unwrap() = None;
+        self.missions.lock().unwrap().clear();
+        *self.search.lock().unwrap() = None;
+        *self.coverage.lock().unwrap() = None;
+        self.detections.lock().unwrap().clear();
+        self.reports.lock().unwrap().clear();
+        self.events.publish(Event::Reset);
+        self.record(Input::Reset);
+
+        println!("Simulation reset");
+        run_drones(self);
+        Ok(())
+    }
 
-    Some(lines)
-}
+    // Asks the drone for its routing table. The answer shows up in routing_table once the
+    // drone has replied, the returned time tells a fresh answer apart from an old one.
+    pub fn request_routing_table(&self, id: usize) -> Result<Instant, SimulatorError> {
+        self.check_known(id)?;
+        let requested = Instant::now();
+        self.send_to_drone(&Packet::RoutingTableRequest, id)?;
+        Ok(requested)
+    }
 
-fn make_drones(lines: Vec<String>) -> Arc<Mutex<Vec<Drone>>> {
-    let drones: Arc<Mutex<Vec<Drone>>> = Arc::new(Mutex::new(Vec::new()));
+    pub fn routing_table(&self, id: usize, since: Instant) -> Option<RoutingTableReport> {
+        self.routing_tables
+            .lock()
+            .unwrap()
+            .get(&id)
+            .filter(|report| report.received >= since)
+            .cloned()
+    }
 
-    let num_of_drones = lines.len() - 1;
-    for i in 1..=num_of_drones {
-        let data: Vec<usize> = lines[i]
-            .split_whitespace()
-            .map(|c| c.parse().expect("File not formated correctly"))
-            .collect();
+    fn record(&self, input: Input) {
+        let Some(recorder) = &self.recorder else {
+            return;
+        };
+        if let Err(e) = recorder.lock().unwrap().record(&input) {
+            println!("Could not record {:?}: {}", input, e);
+        }
+    }
 
-        let id = data[0];
-        let x_coordinates = data[1] as f32;
-        let y_coordinates = data[2] as f32;
+    fn check_known(&self, id: usize) -> Result<(), SimulatorError> {
+        if self
+            .specs
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|drone| drone.id == id)
+        {
+            Ok(())
+        } else {
+            Err(SimulatorError::UnknownDrone(id))
+        }
+    }
 
-        let drone = Drone::new(id, x_coordinates, y_coordinates);
+    fn forget_drone(&self, id: usize) {
+        self.specs.lock().unwrap().retain(|drone| drone.id != id);
+        self.drones.lock().unwrap().retain(|drone| drone.id != id);
+        self.edges
+            .lock()
+            .unwrap()
+            .retain(|(drone, neighbor)| *drone != id && neighbor.id != id);
+        self.routing_tables.lock().unwrap().remove(&id);
+        self.neighbors.lock().unwrap().remove(&id);
+        self.missions.lock().unwrap().remove(&id);
+        self.events.publish(Event::DroneRemoved { id });
+    }
 
-        drones.lock().unwrap().push(drone);
+    fn send_to_drone(&self, packet: &Packet, id: usize) -> io::Result<()> {
+        self.commands
+            .send_to(&packet.encode()?, drone_address(id)?)?;
+        Ok(())
     }
 
-    drones
-}
+    // Passes the drones' traffic for each other through the radio model, like the engine
+    // does, with the drones' last reported positions
+    fn start_relay_thread(&self, socket: UdpSocket, radio: RadioConfig) {
+        let drones = self.drones.clone();
 
-fn make_graph(lines: Vec<String>, drones: Vec<Drone>) -> Graph {
-    let first_line: Vec<&str> = lines[0].split_whitespace().collect();
-    let nodes: usize = first_line[0].parse().expect("File not formated correctly");
-    let edges: usize = first_line[1].parse().expect("File not formated correctly");
+        thread::spawn(move || {
+            let started = Instant::now();
+            let mut radio = RadioModel::new(radio);
+            let mut buffer = [0u8; relay::HEADER_SIZE + MAX_PACKET_SIZE];
+            loop {
+                let now = started.elapsed();
+                for (id, data) in radio.arrived(now) {
+                    let sent = drone_address(id).and_then(|address| socket.send_to(&data, address));
+                    if let Err(e) = sent {
+                        println!("Could not relay to drone {}: {}", id, e);
+                    }
+                }
 
-    assert!(lines.len() == edges + 1);
+                let timeout = radio
+                    .next_arrival()
+                    .map_or(RELAY_IDLE_TIMEOUT, |arrival| arrival.saturating_sub(now))
+                    // A zero read timeout is rejected by the socket
+                    .clamp(Duration::from_millis(1), RELAY_IDLE_TIMEOUT);
+                if let Err(e) = socket.set_read_timeout(Some(timeout)) {
+                    println!("Could not wait for relayed traffic: {}", e);
+                }
+                let (size, source) = match socket.recv_from(&mut buffer) {
+                    Ok(received) => received,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    Err(e) => {
+                        println!("Could not receive relayed traffic: {}", e);
+                        continue;
+                    }
+                };
+                let (Some(from), Some((to, data))) =
+                    (drone_id(&source), relay::unframe(&buffer[..size]))
+                else {
+                    println!("Dropping relayed datagram from {}", source);
+                    continue;
+                };
+
+                let drones = drones.lock().unwrap().clone();
+                let Some(sender) = drones.iter().find(|drone| drone.id == from) else {
+                    continue;
+                };
+                let receivers: Vec<DroneData> = drones
+                    .iter()
+                    .filter(|drone| match to {
+                        Some(to) => drone.id == to,
+                        None => drone.id != from,
+                    })
+                    .cloned()
+                    .collect();
+                radio.transmit(started.elapsed(), sender, &receivers, data);
+            }
+        });
+    }
 
-    let mut graph = Graph::with_nodes(nodes);
-    for i in 1..=edges {
-        let data: Vec<usize> = lines[i]
-            .split_whitespace()
-            .map(|c| c.parse().expect("File not formated correctly"))
-            .collect();
+    fn start_reader_thread(&self, socket: UdpSocket, mut capture: Option<Capture>) {
+        let drones_clone = self.drones.clone();
+        let finished_clone = self.finished.clone();
+        let routing_tables = self.routing_tables.clone();
+        let edges = self.edges.clone();
+        let events = self.events.clone();
+        let known_neighbors = self.neighbors.clone();
+        let missions = self.missions.clone();
+        let coverage = self.coverage.clone();
+        let persons = self.persons.clone();
+        let detections = self.detections.clone();
+        let reports = self.reports.clone();
+        // Drones that join during a search get their share once they listen
+        let sim = self.clone();
 
-        let drone = drones[data[1]].clone();
-        graph.drones[data[0]].push(drone);
+        thread::spawn(move || {
+            let mut bad_packets: u64 = 0;
+            loop {
+                let mut buffer = [0u8; MAX_PACKET_SIZE];
+                let size = match socket.recv_from(&mut buffer) {
+                    Ok((size, _)) => size,
+                    Err(e) => {
+                        println!("Could not receive from drones: {}", e);
+                        continue;
+                    }
+                };
+                match Packet::decode(&buffer[..size]) {
+                    Ok(Packet::Register { id, .. }) => {
+                        println!("Drone {} registered", id);
+                        let edges = edges.lock().unwrap().clone();
+                        if let Err(e) = configure_drone(&socket, id, &edges) {
+                            println!("Could not configure drone {}: {}", id, e);
+                        }
+                        if sim.search.lock().unwrap().is_some()
+                            && !missions.lock().unwrap().contains_key(&id)
+                        {
+                            sim.split_search_again();
+                        }
+                    }
+                    // The simulation keeps running so drones can still be added or reset
+                    Ok(Packet::Finished { id }) => {
+                        println!("Target reached by drone {}", id);
+                        *finished_clone.lock().unwrap() = Some(id);
+                        events.publish(Event::Finished { id });
+                    }
+                    // The search ends when the base station has a report, the base station
+                    // calls the drones home itself
+                    Ok(Packet::ReportReceived(report)) => {
+                        let id = report.finder;
+                        println!("Base station received a report from drone {}", id);
+                        *sim.search.lock().unwrap() = None;
+                        missions.lock().unwrap().clear();
+                        reports.lock().unwrap().push(report);
+                        let mut finished = finished_clone.lock().unwrap();
+                        if finished.is_none() {
+                            *finished = Some(id);
+                            events.publish(Event::Finished { id });
+                        }
+                    }
+                    Ok(Packet::Position(data)) => {
+                        let mut drones = drones_clone.lock().unwrap();
+                        for drone in &mut *drones {
+                            if drone.id == data.id {
+                                drone.x = data.x;
+                                drone.y = data.y;
+                                events.publish(Event::Position(data.clone()));
+                            }
+                        }
+                        let position = Coordinate {
+                            x: data.x,
+                            y: data.y,
+                        };
+                        if let Some(coverage) = &mut *coverage.lock().unwrap() {
+                            coverage.mark(data.id, &position);
+                        }
+                        let frame = persons.frame(&position);
+                        let sent = drone_address(data.id)
+                            .and_then(|address| socket.send_to(&frame.encode()?, address));
+                        if let Err(e) = sent {
+                            println!("Could not send camera frame to drone {}: {}", data.id, e);
+                        }
+                    }
+                    Ok(Packet::Detection {
+                        id,
+                        position,
+                        confidence,
+                    }) => {
+                        let report = persons.identify(id, position, confidence);
+                        match report.person {
+                            Some(person) => println!(
+                                "Drone {} found missing person {} (confidence {:.2})",
+                                id,
+                                person + 1,
+                                confidence
+                            ),
+                            None => println!(
+                                "Drone {} reported a false positive (confidence {:.2})",
+                                id, confidence
+                            ),
+                        }
+                        events.publish(Event::Detection(report.clone()));
+                        detections.lock().unwrap().push(report);
+                    }
+                    Ok(Packet::MissionComplete { id }) => {
+                        println!("Drone {} has completed its mission", id);
+                        missions.lock().unwrap().remove(&id);
+                        events.publish(Event::MissionComplete { id });
+                    }
+                    Ok(Packet::Neighbors { id, neighbors }) => {
+                        known_neighbors
+                            .lock()
+                            .unwrap()
+                            .insert(id, neighbors.clone());
+                        events.publish(Event::Neighbors { id, neighbors });
+                    }
+                    Ok(Packet::PacketSent {
+                        sender,
+                        receiver,
+                        kind,
+                    }) => {
+                        events.publish(Event::Packet {
+                            sender,
+                            receiver,
+                            kind,
+                        });
+                    }
+                    Ok(Packet::Trace {
+                        sender,
+                        receiver,
+                        length,
+                        data,
+                    }) => {
+                        if let Some(capture) = &mut capture {
+                            let now = SystemTime::now()
+                                .duration_since(SystemTime::UNIX_EPOCH)
+                                .unwrap_or_default();
+                            if let Err(e) = capture.record(now, sender, receiver, &data, length) {
+                                println!("Could not write packet capture: {}", e);
+                            }
+                        }
+                        let kind = capture::kind(&data, length).to_string();
+                        events.publish(Event::Packet {
+                            sender,
+                            receiver,
+                            kind,
+                        });
+                    }
+                    Ok(Packet::RoutingTableReport {
+                        id,
+                        neighbors,
+                        routes,
+                    }) => {
+                        let report = RoutingTableReport {
+                            id,
+                            neighbors,
+                            routes,
+                            received: Instant::now(),
+                        };
+                        routing_tables.lock().unwrap().insert(id, report);
+                    }
+                    Ok(other) => println!("Ignoring unexpected {} packet", other.name()),
+                    Err(e) => {
+                        bad_packets += 1;
+                        println!("Dropping malformed packet ({} so far): {}", bad_packets, e);
+                    }
+                }
+            }
+        });
     }
-    graph
-}
 
-fn run_drones(sim: &Simulator) {
-    for drone in &*sim.drones.lock().unwrap() {
-        run_drone_windows(drone.id, drone.x, drone.y);
+    pub fn get_drones(&self) -> Vec<DroneData> {
+        self.drones.lock().unwrap().clone()
     }
-    println!("Waiting for drones to start");
-    thread::sleep(std::time::Duration::from_secs(10));
-    println!("Adding edges");
-    make_edges(sim);
-}
 
-fn get_path_to_drone() -> String {
-    let current_dir = env::current_dir().expect("could not find the correct file path");
-    let project_path = PathBuf::from(current_dir)
-        .join("../drone")
-        .canonicalize()
-        .expect("could not modefy the file path");
-    let path: String = format!("{}", project_path.display());
-    path
-}
+    pub fn update_drones(&self) -> io::Result<()> {
+        for drone in &*self.drones.lock().unwrap() {
+            if let Err(e) = self.send_to_drone(&Packet::RequestPosition, drone.id) {
+                println!("Could not request position from drone {}: {}", drone.id, e);
+            }
+        }
+        Ok(())
+    }
 
-fn run_drone_windows(id: usize, x: f32, y: f32) -> std::io::Result<()> {
-    let project_path = get_path_to_drone();
-    let trimmed_path = project_path.trim_start_matches(&['\\', '?'][..]);
+    pub fn is_finished(&self) -> bool {
+        self.finished.lock().unwrap().is_some()
+    }
 
-    // Command to run your application
-    let command = format!("cargo run {} {} {}", id, x, y);
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
 
-    // Run the command in a new cmd window
-    let output = Command::new("cmd.exe")
-        .arg("/C")
-        .arg("start")
-        .arg("cmd.exe")
-        .arg("/K")
-        .arg(&command)
-        .current_dir(trimmed_path) // Set the working directory to your project's directory
-        .spawn()?;
+    // Events that bring a new live view up to date: where the drones are, who their
+    // neighbors are, which missions they fly and whether the target has been found
+    pub fn snapshot(&self) -> Vec<Event> {
+        let mut events: Vec<Event> = self
+            .get_drones()
+            .into_iter()
+            .map(Event::DroneAdded)
+            .collect();
+        for (id, neighbors) in &*self.neighbors.lock().unwrap() {
+            events.push(Event::Neighbors {
+                id: *id,
+                neighbors: neighbors.clone(),
+            });
+        }
+        for (id, mission) in &*self.missions.lock().unwrap() {
+            events.push(Event::Mission {
+                id: *id,
+                mission: mission.clone(),
+            });
+        }
+        if let Some(id) = *self.finished.lock().unwrap() {
+            events.push(Event::Finished { id });
+        }
+        events
+    }
+}
 
-    Ok(())
+fn initial_targets(scenario: &Scenario) -> Vec<Coordinate> {
+    scenario
+        .targets
+        .iter()
+        .map(|target| target.position.clone())
+        .collect()
 }
 
-fn make_edges(sim: &Simulator) {
-    let standard_port = 8080;
-    let url = "127.0.0.1:";
-    let socket = UdpSocket::bind("127.0.0.1:7877").expect("Could not bind socket");
-    let message = "ADD_NEIGHBOR";
-    for (drone, _) in (0..sim.graph.drones.len()).enumerate() {
-        for (edge, _) in (0..sim.graph.drones[drone].len()).enumerate() {
-            let port = standard_port + drone;
-            let id = sim.graph.drones[drone][edge].id;
-            let x = sim.graph.drones[drone][edge].x;
-            let y = sim.graph.drones[drone][edge].y;
-            socket
-                .send_to(
-                    format!("{} {} {} {}", message, id, x, y).as_bytes(),
-                    format!("{}{}", url, port),
-                )
-                .unwrap();
+fn run_drones(sim: &Simulator) {
+    let Some(launcher) = &sim.launcher else {
+        return;
+    };
+    for drone in &*sim.specs.lock().unwrap() {
+        if let Err(e) = launcher.lock().unwrap().spawn(drone) {
+            println!("Could not start drone {}: {}", drone.id, e);
         }
     }
+    println!("Waiting for drones to register");
+}
+
+// Acknowledges the registration and sends the drone its neighbors. A drone registers
+// again if the acknowledgement is lost, and adding a known neighbor does nothing.
+fn configure_drone(socket: &UdpSocket, id: usize, edges: &[(usize, DroneData)]) -> io::Result<()> {
+    let address = drone_address(id)?;
+    socket.send_to(&Packet::RegisterAck.encode()?, address)?;
+    for (_, neighbor) in edges.iter().filter(|(drone, _)| *drone == id) {
+        socket.send_to(
+            &Packet::AddNeighbor {
+                id: neighbor.id,
+                position: Coordinate {
+                    x: neighbor.x,
+                    y: neighbor.y,
+                },
+            }
+            .encode()?,
+            address,
+        )?;
+    }
+    Ok(())
 }
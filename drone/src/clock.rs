@@ -0,0 +1,67 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// Source of the current time for timers, so drones can run on simulated time
+pub trait Clock {
+    fn now(&self) -> Instant;
+    // A number that tells a drone started now apart from earlier drones with the same id
+    fn boot_epoch(&self) -> u32;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn boot_epoch(&self) -> u32 {
+        rand::random()
+    }
+}
+
+// Time that only moves when it is advanced. Clones share the same time.
+#[derive(Clone)]
+pub struct SimClock {
+    start: Instant,
+    elapsed: Rc<Cell<Duration>>,
+    // Counted instead of random, so simulated runs stay reproducible
+    boots: Rc<Cell<u32>>,
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        SimClock {
+            start: Instant::now(),
+            elapsed: Rc::new(Cell::new(Duration::ZERO)),
+            boots: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        SimClock::new()
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+
+    fn boot_epoch(&self) -> u32 {
+        let epoch = self.boots.get();
+        self.boots.set(epoch.wrapping_add(1));
+        epoch
+    }
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+const SPEED: f32 = 5.0;
+const COMMUNICATION_RADIUS: f32 = 150.0;
+const HELLO_INTERVAL_MS: u64 = 1000;
+const ACTIVE_ROUTE_TIMEOUT_MS: u64 = 3000;
+const ALLOWED_HELLO_LOSS: u32 = 2;
+const RETRANSMIT_TIMEOUT_MS: u64 = 200;
+const MAX_RETRIES: u32 = 4;
+// Invalid routes are kept this many timeouts before they are deleted
+const DELETE_PERIOD_FACTOR: u32 = 5;
+const ALTITUDE: f32 = 30.0;
+const FIELD_OF_VIEW_DEGREES: f32 = 60.0;
+const DETECTION_PROBABILITY: f32 = 0.9;
+const FALSE_POSITIVE_RATE: f32 = 0.01;
+const REPORT_THRESHOLD: f32 = 0.5;
+// Enough for both ends of a link to move a step before hearing of each other
+const SAFETY_MARGIN: f32 = 15.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroneConfig {
+    // Distance covered in one move
+    pub speed: f32,
+    pub communication_radius: f32,
+    pub hello_interval: Duration,
+    pub active_route_timeout: Duration,
+    pub allowed_hello_loss: u32,
+    // Send unicast traffic through the acknowledged and retransmitted channel
+    pub reliable_delivery: bool,
+    pub retransmit_timeout: Duration,
+    pub max_retries: u32,
+    // Report neighbor changes and sent packets to the simulator for the live view
+    pub telemetry: bool,
+    // Send the simulator a copy of every packet for packet capture, instead of just its type
+    pub trace: bool,
+    // Height above the ground, in the same units as positions
+    pub altitude: f32,
+    // Full opening angle of the thermal camera
+    pub field_of_view_degrees: f32,
+    // Chance of spotting a person right below the drone, before the altitude is counted in
+    pub detection_probability: f32,
+    // Chance that a camera frame shows a person where there is none
+    pub false_positive_rate: f32,
+    // Each drone's sensor noise is seeded with this plus its id
+    pub sensor_seed: u64,
+    // Detections at least this certain are reported to the base station
+    pub report_threshold: f32,
+    // Id of the base station node. The drone with this id is the base station.
+    pub base_station: Option<usize>,
+    // Drones keep the links that hold the swarm together this far inside the radio range
+    pub safety_margin: f32,
+    // Print every packet and decision. The drone program turns this on, see DRONE_VERBOSE.
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+impl Default for DroneConfig {
+    fn default() -> Self {
+        DroneConfig {
+            speed: SPEED,
+            communication_radius: COMMUNICATION_RADIUS,
+            hello_interval: Duration::from_millis(HELLO_INTERVAL_MS),
+            active_route_timeout: Duration::from_millis(ACTIVE_ROUTE_TIMEOUT_MS),
+            allowed_hello_loss: ALLOWED_HELLO_LOSS,
+            reliable_delivery: false,
+            retransmit_timeout: Duration::from_millis(RETRANSMIT_TIMEOUT_MS),
+            max_retries: MAX_RETRIES,
+            telemetry: true,
+            trace: false,
+            altitude: ALTITUDE,
+            field_of_view_degrees: FIELD_OF_VIEW_DEGREES,
+            detection_probability: DETECTION_PROBABILITY,
+            false_positive_rate: FALSE_POSITIVE_RATE,
+            sensor_seed: 0,
+            report_threshold: REPORT_THRESHOLD,
+            base_station: None,
+            safety_margin: SAFETY_MARGIN,
+            verbose: false,
+        }
+    }
+}
+
+impl DroneConfig {
+    // Reads overrides from DRONE_SPEED, DRONE_COMMUNICATION_RADIUS, DRONE_HELLO_INTERVAL_MS,
+    // DRONE_ACTIVE_ROUTE_TIMEOUT_MS, DRONE_ALLOWED_HELLO_LOSS, DRONE_RELIABLE,
+    // DRONE_RETRANSMIT_TIMEOUT_MS, DRONE_MAX_RETRIES, DRONE_TELEMETRY, DRONE_TRACE,
+    // DRONE_ALTITUDE, DRONE_FIELD_OF_VIEW, DRONE_DETECTION_PROBABILITY,
+    // DRONE_FALSE_POSITIVE_RATE, DRONE_SENSOR_SEED, DRONE_REPORT_THRESHOLD,
+    // DRONE_BASE_STATION, DRONE_SAFETY_MARGIN and DRONE_VERBOSE, falling back to the
+    // defaults.
+    pub fn from_env() -> Self {
+        let default = DroneConfig::default();
+        DroneConfig {
+            speed: read_env("DRONE_SPEED").unwrap_or(default.speed),
+            communication_radius: read_env("DRONE_COMMUNICATION_RADIUS")
+                .unwrap_or(default.communication_radius),
+            hello_interval: read_env("DRONE_HELLO_INTERVAL_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.hello_interval),
+            active_route_timeout: read_env("DRONE_ACTIVE_ROUTE_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.active_route_timeout),
+            allowed_hello_loss: read_env("DRONE_ALLOWED_HELLO_LOSS")
+                .unwrap_or(default.allowed_hello_loss),
+            reliable_delivery: read_env("DRONE_RELIABLE").unwrap_or(default.reliable_delivery),
+            retransmit_timeout: read_env("DRONE_RETRANSMIT_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.retransmit_timeout),
+            max_retries: read_env("DRONE_MAX_RETRIES").unwrap_or(default.max_retries),
+            telemetry: read_env("DRONE_TELEMETRY").unwrap_or(default.telemetry),
+            trace: read_env("DRONE_TRACE").unwrap_or(default.trace),
+            altitude: read_env("DRONE_ALTITUDE").unwrap_or(default.altitude),
+            field_of_view_degrees: read_env("DRONE_FIELD_OF_VIEW")
+                .unwrap_or(default.field_of_view_degrees),
+            detection_probability: read_env("DRONE_DETECTION_PROBABILITY")
+                .unwrap_or(default.detection_probability),
+            false_positive_rate: read_env("DRONE_FALSE_POSITIVE_RATE")
+                .unwrap_or(default.false_positive_rate),
+            sensor_seed: read_env("DRONE_SENSOR_SEED").unwrap_or(default.sensor_seed),
+            report_threshold: read_env("DRONE_REPORT_THRESHOLD")
+                .unwrap_or(default.report_threshold),
+            base_station: read_env("DRONE_BASE_STATION").or(default.base_station),
+            safety_margin: read_env("DRONE_SAFETY_MARGIN").unwrap_or(default.safety_margin),
+            verbose: read_env("DRONE_VERBOSE").unwrap_or(default.verbose),
+        }
+    }
+
+    // A neighbor is lost when this many HELLO intervals pass without hearing from it
+    pub fn neighbor_timeout(&self) -> Duration {
+        self.hello_interval * self.allowed_hello_loss
+    }
+
+    pub fn delete_period(&self) -> Duration {
+        self.active_route_timeout.max(self.hello_interval) * DELETE_PERIOD_FACTOR
+    }
+
+    // How far from the point right below the drone the thermal camera sees
+    pub fn footprint_radius(&self) -> f32 {
+        self.altitude * (self.field_of_view_degrees.to_radians() / 2.0).tan()
+    }
+}
+
+pub fn read_env<T: FromStr>(name: &str) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            println!("Ignoring invalid value for {}: {}", name, value);
+            None
+        }
+    }
+}
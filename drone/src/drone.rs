@@ -1,76 +1,183 @@
-use serde::{Deserialize, Serialize};
-use std::io::{self};
-use std::net::{SocketAddr, UdpSocket};
-
-const STANDARD_PORT: u32 = 8080;
-const COMMUNICATION_RADIUS: f32 = 150.0;
-const DRONE_SPEED: f32 = 5.0;
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Coordinate {
-    pub x: f32,
-    pub y: f32,
-}
+use mesh_proto::geometry::distance;
+use mesh_proto::{Coordinate, DroneData, Packet, RouteEntry, SearchReport, MAX_PACKET_SIZE};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct DroneData {
-    id: usize,
-    x: f32,
-    y: f32,
-}
+use crate::clock::{Clock, SystemClock};
+use crate::config::DroneConfig;
+use crate::error::DroneError;
+use crate::formation::Formation;
+use crate::mission::MissionState;
+use crate::reliable::{ReliableChannel, Timeout};
+use crate::routing::{Route, RoutingTable};
+use crate::sensor::ThermalSensor;
+use crate::transport::{Endpoint, Transport};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RoutingTable {
-    neighbors: Vec<Neighbor>,
+// How often a drone asks the simulator to register it until it answers
+const REGISTER_INTERVAL: Duration = Duration::from_millis(500);
+// How long the receive loop blocks before timers are checked
+const TIMER_INTERVAL: Duration = Duration::from_millis(100);
+// How often a drone flying home moves one step, like the simulator's steps
+const FLIGHT_STEP_INTERVAL: Duration = Duration::from_millis(100);
+// How long a finder waits for the base station to acknowledge before sending the report again
+const REPORT_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+// The frontend's target marks where the missing person is, so reaching it is a sure find
+const TARGET_CONFIDENCE: f32 = 1.0;
+// Flooded by the base station once someone has been found
+const RECALL_MESSAGE: &str = "recall";
+// How close a drone held back by the formation has to get to a waypoint to count it as reached
+const WAYPOINT_TOLERANCE: f32 = 0.01;
+
+// Prints what the drone does when its config asks for it. Errors are always printed.
+macro_rules! log {
+    ($drone:expr, $($arg:tt)*) => {
+        if $drone.config.verbose {
+            println!($($arg)*);
+        }
+    };
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Neighbor {
-    id: usize,
-    position: Coordinate,
+// A search report on its way to the base station
+struct PendingReport {
+    report: SearchReport,
+    sent: Instant,
+    acknowledged: bool,
 }
 
 pub struct Drone {
     id: usize,
     position: Coordinate,
-    socket: UdpSocket,
-    simulator_address: SocketAddr,
+    // Where the drone took off, it lands here when the search is over
+    home: Coordinate,
+    transport: Box<dyn Transport>,
     routing_table: RoutingTable,
     go_home: bool,
+    // Set once the drone is back home and has nothing left to deliver
+    landed: bool,
+    last_flight_step: Option<Instant>,
+    report: Option<PendingReport>,
+    // Finders the base station has received a report from
+    received_reports: BTreeSet<usize>,
+    // Set when the simulator removes the drone, it then leaves without reporting anything
+    shutdown: bool,
+    sequence_number: u32,
+    broadcast_id: u32,
+    seen_requests: HashMap<(usize, u32), Instant>,
+    flood_sequence: u32,
+    // Flooded messages already forwarded, keyed by origin and sequence number
+    seen_floods: HashMap<(usize, u32), Instant>,
+    pending_data: BTreeMap<usize, Vec<Packet>>,
+    route_requests: BTreeMap<usize, Instant>,
+    last_hello: Option<Instant>,
+    // Neighbors as last reported to the simulator
+    reported_neighbors: Vec<usize>,
+    // Coverage path the drone is flying, if the simulator gave it one
+    mission: Option<MissionState>,
+    sensor: ThermalSensor,
+    // Holds the drone back from steps that would cut the swarm apart
+    formation: Formation,
+    // Where the drone has reported seeing someone, so it reports each sighting once
+    detections: Vec<Coordinate>,
+    registered: bool,
+    last_register: Option<Instant>,
+    // Datagrams that could not be decoded or handled
+    bad_packets: u64,
+    reliable: ReliableChannel,
+    config: DroneConfig,
+    // When the drone took off, search reports say how long it had been flying
+    started: Instant,
+    clock: Rc<dyn Clock>,
 }
 
 impl Drone {
     pub fn new(
         id: usize,
         position: Coordinate,
-        simulator_address: SocketAddr,
+        transport: impl Transport + 'static,
         go_home: bool,
-    ) -> io::Result<Drone> {
-        let port = STANDARD_PORT + id as u32;
-        let socket = UdpSocket::bind(format!("0.0.0.0:{}", port))?;
-        Ok(Drone {
+        config: DroneConfig,
+    ) -> Drone {
+        Drone::with_clock(
             id,
             position,
-            socket,
-            simulator_address,
-            routing_table: RoutingTable {
-                neighbors: Vec::new(),
-            },
+            transport,
             go_home,
-        })
+            config,
+            Rc::new(SystemClock),
+        )
+    }
+
+    pub fn with_clock(
+        id: usize,
+        position: Coordinate,
+        transport: impl Transport + 'static,
+        go_home: bool,
+        config: DroneConfig,
+        clock: Rc<dyn Clock>,
+    ) -> Drone {
+        Drone {
+            id,
+            home: position.clone(),
+            position,
+            transport: Box::new(transport),
+            routing_table: RoutingTable::new(&config, clock.clone()),
+            go_home,
+            landed: false,
+            last_flight_step: None,
+            report: None,
+            received_reports: BTreeSet::new(),
+            shutdown: false,
+            sequence_number: 0,
+            broadcast_id: 0,
+            seen_requests: HashMap::new(),
+            flood_sequence: 0,
+            seen_floods: HashMap::new(),
+            pending_data: BTreeMap::new(),
+            route_requests: BTreeMap::new(),
+            last_hello: None,
+            reported_neighbors: Vec::new(),
+            mission: None,
+            sensor: ThermalSensor::new(id, &config),
+            formation: Formation::new(&config),
+            detections: Vec::new(),
+            registered: false,
+            last_register: None,
+            bad_packets: 0,
+            reliable: ReliableChannel::new(
+                config.retransmit_timeout,
+                config.max_retries,
+                clock.clone(),
+                {
+                    let verbose = config.verbose;
+                    move |to, packet| {
+                        if verbose {
+                            println!("Drone {} gave up delivering to {}: {:?}", id, to, packet)
+                        }
+                    }
+                },
+            ),
+            config,
+            started: clock.now(),
+            clock,
+        }
     }
 
-    fn move_towards(&mut self, target: &Coordinate) {
+    fn move_towards(&mut self, target: &Coordinate) -> Result<(), DroneError> {
+        if self.is_base_station() || self.go_home {
+            log!(self, "Not moving towards {:?}, staying on course", target);
+            return Ok(());
+        }
         let dx = target.x - self.position.x;
         let dy = target.y - self.position.y;
         let distance = (dx * dx + dy * dy).sqrt();
 
-        let steps = (distance / DRONE_SPEED).ceil() as u32;
+        let steps = (distance / self.config.speed).ceil() as u32;
         let mut step_x = dx / steps as f32;
         let mut step_y = dy / steps as f32;
 
         if step_x.is_nan() && step_y.is_nan() {
-            self.go_home = true;
+            return self.found(target.clone(), TARGET_CONFIDENCE);
         }
         if step_x.is_nan() {
             step_x = 0.0;
@@ -79,34 +186,301 @@ impl Drone {
             step_y = 0.0;
         }
 
-        let furthes_neighbor_from_target = self.find_furthest_neighbor_from_target(target);
-        if let Some(neighbor) = furthes_neighbor_from_target {
-            if self.calculate_distance(
-                step_x + self.position.x,
-                step_y + self.position.y,
-                neighbor.position.x,
-                neighbor.position.y,
-            ) > COMMUNICATION_RADIUS
-            {
-                self.send_move_request(&neighbor, target);
-            } else {
-                println!("Moving towards target: {:?}", target);
-                self.move_self(step_x, step_y);
-            }
-        } else {
-            self.move_self(step_x, step_y);
-            println!("No neighbors found to relay move request. Moving self");
+        let step = self.formation.constrain(
+            &self.position,
+            (step_x, step_y),
+            &self.routing_table.neighbors,
+            self.clock.now(),
+        );
+        if step != (step_x, step_y) {
+            log!(self, "Holding formation, stepping {:?} instead", step);
         }
+        log!(self, "Moving towards target: {:?}", target);
+        self.move_self(step.0, step.1)
     }
 
-    fn move_self(&mut self, step_x: f32, step_y: f32) {
+    fn move_self(&mut self, step_x: f32, step_y: f32) -> Result<(), DroneError> {
         self.position.x += step_x;
         self.position.y += step_y;
 
         // Update position
-        self.send_position_to_simulator();
         self.update_neighbors();
-        println!("Current position: {:?}", self.position);
+        self.check_links();
+        log!(self, "Current position: {:?}", self.position);
+        self.send_position_to_simulator()
+    }
+
+    // Flies one step's worth of the mission, across as many waypoints as the speed allows
+    fn mission_step(&mut self) -> Result<(), DroneError> {
+        let Some(mission) = &self.mission else {
+            log!(self, "No mission to step");
+            return Ok(());
+        };
+        // Where one move takes the drone along the path, and how many waypoints it passes
+        let mut budget = self.config.speed;
+        let mut position = self.position.clone();
+        let mut passed = 0;
+        for waypoint in mission.upcoming() {
+            if budget <= 0.0 {
+                break;
+            }
+            let dx = waypoint.x - position.x;
+            let dy = waypoint.y - position.y;
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance <= budget {
+                position = waypoint.clone();
+                budget -= distance;
+                passed += 1;
+            } else {
+                position.x += dx / distance * budget;
+                position.y += dy / distance * budget;
+                budget = 0.0;
+            }
+        }
+        let wanted = (position.x - self.position.x, position.y - self.position.y);
+        let step = self.formation.constrain(
+            &self.position,
+            wanted,
+            &self.routing_table.neighbors,
+            self.clock.now(),
+        );
+        let landing = Coordinate {
+            x: self.position.x + step.0,
+            y: self.position.y + step.1,
+        };
+
+        let Some(mission) = &mut self.mission else {
+            return Ok(());
+        };
+        if step == wanted {
+            for _ in 0..passed {
+                mission.advance();
+            }
+        } else {
+            log!(self, "Holding formation, stepping {:?} instead", step);
+            // Held back, so at most the waypoint it was heading for can have been reached
+            if mission
+                .target()
+                .is_some_and(|waypoint| distance(waypoint, &landing) < WAYPOINT_TOLERANCE)
+            {
+                mission.advance();
+            }
+        }
+        let complete = mission.target().is_none();
+        self.move_self(step.0, step.1)?;
+
+        if complete {
+            log!(self, "Mission complete");
+            self.mission = None;
+            self.send_to_simulator(&Packet::MissionComplete { id: self.id })?;
+        }
+        Ok(())
+    }
+
+    // Runs the camera frame through the sensor and reports anyone it spots that it has not
+    // reported before
+    fn scan(&mut self, sources: &[Coordinate]) -> Result<(), DroneError> {
+        let radius = self.sensor.footprint_radius();
+        for detection in self.sensor.scan(&self.position, sources) {
+            let seen = self.detections.iter().any(|earlier| {
+                self.calculate_distance(
+                    earlier.x,
+                    earlier.y,
+                    detection.position.x,
+                    detection.position.y,
+                ) <= radius
+            });
+            if seen {
+                continue;
+            }
+            log!(
+                self,
+                "Thermal sensor sees someone at {:?} (confidence {:.2})",
+                detection.position,
+                detection.confidence
+            );
+            let report = Packet::Detection {
+                id: self.id,
+                position: detection.position.clone(),
+                confidence: detection.confidence,
+            };
+            self.send_to_simulator(&report)?;
+            self.detections.push(detection.position.clone());
+            if detection.confidence >= self.config.report_threshold {
+                self.found(detection.position, detection.confidence)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_base_station(&self) -> bool {
+        self.config.base_station == Some(self.id)
+    }
+
+    // Stops searching, reports the find to the base station and flies home. Only the
+    // first find is reported.
+    fn found(&mut self, position: Coordinate, confidence: f32) -> Result<(), DroneError> {
+        if self.report.is_some() || self.go_home || self.is_base_station() {
+            return Ok(());
+        }
+        let report = SearchReport {
+            finder: self.id,
+            position,
+            time: self.clock.now().duration_since(self.started),
+            confidence,
+        };
+        log!(self, "Found someone: {:?}", report);
+        self.mission = None;
+        self.go_home = true;
+
+        let Some(base_station) = self.config.base_station else {
+            // Without a base station the simulator is told directly
+            self.report = Some(PendingReport {
+                report,
+                sent: self.clock.now(),
+                acknowledged: true,
+            });
+            return self.send_finished_to_simulator();
+        };
+        self.report = Some(PendingReport {
+            report: report.clone(),
+            sent: self.clock.now(),
+            acknowledged: false,
+        });
+        self.send_routed(
+            Packet::SearchReport {
+                sender: self.id,
+                destination: base_station,
+                report,
+            },
+            base_station,
+        )
+    }
+
+    fn resend_report(&mut self) {
+        let Some(base_station) = self.config.base_station else {
+            return;
+        };
+        let now = self.clock.now();
+        let Some(pending) = &mut self.report else {
+            return;
+        };
+        if pending.acknowledged || now.duration_since(pending.sent) < REPORT_RETRY_INTERVAL {
+            return;
+        }
+        pending.sent = now;
+        let report = Packet::SearchReport {
+            sender: self.id,
+            destination: base_station,
+            report: pending.report.clone(),
+        };
+        log!(
+            self,
+            "No acknowledgement from the base station, sending the report again"
+        );
+        if let Err(e) = self.send_routed(report, base_station) {
+            println!("Could not send the report again: {}", e);
+        }
+    }
+
+    fn receive_search_report(
+        &mut self,
+        sender: usize,
+        destination: usize,
+        report: SearchReport,
+    ) -> Result<(), DroneError> {
+        self.routing_table.update_neighbor_route(sender);
+        if destination != self.id {
+            let packet = Packet::SearchReport {
+                sender: self.id,
+                destination,
+                report: report.clone(),
+            };
+            return self.forward(packet, sender, report.finder, destination);
+        }
+        if !self.is_base_station() {
+            log!(
+                self,
+                "Not the base station, dropping report from {}",
+                report.finder
+            );
+            return Ok(());
+        }
+
+        log!(self, "Base station received report: {:?}", report);
+        let finder = report.finder;
+        let first = self.received_reports.is_empty();
+        if self.received_reports.insert(finder) {
+            self.send_to_simulator(&Packet::ReportReceived(report))?;
+        }
+        // The search is over, call every drone home
+        if first {
+            self.flood_message(RECALL_MESSAGE);
+        }
+        let ack = Packet::SearchAck {
+            sender: self.id,
+            origin: self.id,
+            destination: finder,
+        };
+        self.send_routed(ack, finder)
+    }
+
+    fn receive_search_ack(
+        &mut self,
+        sender: usize,
+        origin: usize,
+        destination: usize,
+    ) -> Result<(), DroneError> {
+        self.routing_table.update_neighbor_route(sender);
+        if destination != self.id {
+            let packet = Packet::SearchAck {
+                sender: self.id,
+                origin,
+                destination,
+            };
+            return self.forward(packet, sender, origin, destination);
+        }
+        if let Some(pending) = &mut self.report {
+            log!(self, "Base station acknowledged the report");
+            pending.acknowledged = true;
+        }
+        Ok(())
+    }
+
+    // One step towards home. The drone lands there once the base station has its report.
+    fn fly_home(&mut self) -> Result<(), DroneError> {
+        let now = self.clock.now();
+        if self
+            .last_flight_step
+            .is_some_and(|last| now.duration_since(last) < FLIGHT_STEP_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_flight_step = Some(now);
+
+        let dx = self.home.x - self.position.x;
+        let dy = self.home.y - self.position.y;
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance > 0.0 {
+            let fraction = distance.min(self.config.speed) / distance;
+            let step = self.formation.constrain(
+                &self.position,
+                (dx * fraction, dy * fraction),
+                &self.routing_table.neighbors,
+                now,
+            );
+            self.move_self(step.0, step.1)?;
+            return Ok(());
+        }
+        if self
+            .report
+            .as_ref()
+            .is_none_or(|pending| pending.acknowledged)
+        {
+            log!(self, "Landed at home");
+            self.landed = true;
+        }
+        Ok(())
     }
 
     fn calculate_distance(&self, x: f32, y: f32, x_2: f32, y_2: f32) -> f32 {
@@ -116,235 +490,893 @@ impl Drone {
     }
 
     fn update_neighbors(&self) {
-        for neighbor in &self.routing_table.neighbors {
-            let message = format!("{} {}", self.position.x, self.position.y);
-            self.send_message(&message, neighbor.id, "UPDATE");
+        let update = Packet::Update {
+            id: self.id,
+            position: self.position.clone(),
+        };
+        self.send_to_neighbors(&update, None);
+        log!(self, "Neighbors updated");
+    }
+
+    fn update_neighbor(&mut self, requester_id: usize, position: Coordinate) {
+        log!(self, "updating neighbor");
+        for neighbor in &mut self.routing_table.neighbors {
+            if neighbor.id == requester_id {
+                neighbor.position = position.clone();
+            }
+        }
+        self.check_links();
+        log!(self, "Finished updating")
+    }
+
+    fn check_links(&mut self) {
+        let broken: Vec<usize> = self
+            .routing_table
+            .neighbors
+            .iter()
+            .filter(|neighbor| {
+                !self.is_within_communication_radius(neighbor.position.x, neighbor.position.y)
+            })
+            .map(|neighbor| neighbor.id)
+            .collect();
+
+        for neighbor in broken {
+            self.handle_link_break(neighbor);
         }
-        println!("Neighbors updated");
     }
 
-    fn update_neighbor(&mut self, message: &str) {
-        let message_parts: Vec<&str> = message.trim().split_whitespace().collect();
+    fn handle_link_break(&mut self, neighbor: usize) {
+        log!(self, "Link to neighbor {} broken", neighbor);
+        self.routing_table.remove_neighbor(neighbor);
+
+        let invalidated = self.routing_table.invalidate_routes_through(neighbor);
+        self.send_route_error(&invalidated);
+    }
+
+    fn send_route_error(&mut self, invalidated: &[Route]) {
+        if invalidated.is_empty() {
+            return;
+        }
 
-        println!("updating neighbor");
-        if let (Ok(requester_id), Ok(requester_x), Ok(requester_y)) = (
-            message_parts[1].parse::<usize>(),
-            message_parts[2].parse::<f32>(),
-            message_parts[3].parse::<f32>(),
-        ) {
-            for neighbor in &mut self.routing_table.neighbors {
-                if neighbor.id == requester_id {
-                    neighbor.position.x = requester_x;
-                    neighbor.position.y = requester_y;
+        let mut precursors = BTreeSet::new();
+        let mut unreachable = Vec::new();
+        for route in invalidated {
+            unreachable.push((route.destination, route.destination_sequence));
+            precursors.extend(route.precursors.iter().copied());
+        }
+
+        let error = Packet::RouteError {
+            sender: self.id,
+            unreachable,
+        };
+        for precursor in precursors {
+            if self.routing_table.is_neighbor(precursor) {
+                if let Err(e) = self.send_reliable(error.clone(), precursor) {
+                    println!("Could not send route error to {}: {}", precursor, e);
                 }
             }
         }
-        println!("Finished updating")
     }
 
-    fn send_position_to_simulator(&self) {
-        let drone_data = DroneData {
+    fn receive_route_error(&mut self, sender: usize, unreachable: &[(usize, u32)]) {
+        let mut invalidated = Vec::new();
+        for &(destination, destination_sequence) in unreachable {
+            if let Some(route) =
+                self.routing_table
+                    .invalidate_route(destination, sender, destination_sequence)
+            {
+                log!(self, "Route to {} invalidated by {}", destination, sender);
+                invalidated.push(route);
+            }
+        }
+        self.send_route_error(&invalidated);
+    }
+
+    fn send_position_to_simulator(&self) -> Result<(), DroneError> {
+        let position = Packet::Position(DroneData::new(self.id, self.position.x, self.position.y));
+
+        self.send_to_simulator(&position)?;
+
+        log!(
+            self,
+            "Sent position update to simulator: {:?}",
+            self.position
+        );
+        Ok(())
+    }
+
+    fn send_routing_table_to_simulator(&self) -> Result<(), DroneError> {
+        let routes = self
+            .routing_table
+            .routes
+            .values()
+            .map(|route| RouteEntry {
+                destination: route.destination,
+                next_hop: route.next_hop,
+                hop_count: route.hop_count,
+                destination_sequence: route.destination_sequence,
+                valid: route.valid,
+            })
+            .collect();
+        let report = Packet::RoutingTableReport {
             id: self.id,
-            x: self.position.x,
-            y: self.position.y,
+            neighbors: self
+                .routing_table
+                .neighbors
+                .iter()
+                .map(|neighbor| neighbor.id)
+                .collect(),
+            routes,
         };
-        let message = serde_json::to_string(&drone_data).unwrap();
+        self.send_to_simulator(&report)?;
+        Ok(())
+    }
 
-        self.socket
-            .send_to(message.as_bytes(), self.simulator_address)
-            .unwrap();
+    fn report_neighbors(&mut self) {
+        let mut neighbors: Vec<usize> = self
+            .routing_table
+            .neighbors
+            .iter()
+            .map(|neighbor| neighbor.id)
+            .collect();
+        neighbors.sort_unstable();
+        if !self.config.telemetry || neighbors == self.reported_neighbors {
+            return;
+        }
 
-        println!("Sent position update to simulator: {:?}", self.position);
+        let report = Packet::Neighbors {
+            id: self.id,
+            neighbors: neighbors.clone(),
+        };
+        match self.send_to_simulator(&report) {
+            Ok(()) => self.reported_neighbors = neighbors,
+            Err(e) => println!("Could not report neighbors to simulator: {}", e),
+        }
+    }
+
+    fn report_packet(&self, packet: &Packet, data: &[u8], receiver: Option<usize>) {
+        let report = if self.config.trace {
+            Packet::trace(self.id, receiver, data)
+        } else if self.config.telemetry {
+            Packet::PacketSent {
+                sender: self.id,
+                receiver,
+                kind: packet.name().to_string(),
+            }
+        } else {
+            return;
+        };
+        if let Err(e) = self.send_to_simulator(&report) {
+            println!("Could not report packet to simulator: {}", e);
+        }
     }
 
-    fn send_finished_to_simulator(&self) {
-        let message = "true";
-        self.socket
-            .send_to(message.as_bytes(), self.simulator_address)
-            .unwrap();
+    fn send_finished_to_simulator(&self) -> Result<(), DroneError> {
+        let finished = Packet::Finished { id: self.id };
+        self.send_to_simulator(&finished)?;
 
-        println!("Sent finished update to simulator: {:?}", self.go_home);
+        log!(
+            self,
+            "Sent finished update to simulator: {:?}",
+            self.go_home
+        );
+        Ok(())
     }
 
-    fn send_move_request(&self, neighbor: &Neighbor, target: &Coordinate) {
-        let move_request = format!("MOVE_REQUEST {} {} {}", self.id, target.x, target.y);
-        let port = STANDARD_PORT + neighbor.id as u32;
-        let neighbor_address: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+    fn is_within_communication_radius(&self, x: f32, y: f32) -> bool {
+        let distance = ((x - self.position.x).powi(2) + (y - self.position.y).powi(2)).sqrt();
+        distance <= self.config.communication_radius
+    }
 
-        self.socket
-            .send_to(move_request.as_bytes(), neighbor_address)
-            .unwrap();
+    fn receive_and_send_message(
+        &mut self,
+        sender: usize,
+        origin: usize,
+        sequence: u32,
+        message: &str,
+    ) {
+        if self
+            .seen_floods
+            .insert((origin, sequence), self.clock.now())
+            .is_some()
+        {
+            log!(
+                self,
+                "Dropping duplicate message {} from {}",
+                sequence,
+                origin
+            );
+            return;
+        }
 
-        println!(
-            "Sent move request to neighbor {}: {:?}",
-            neighbor.id, target
+        log!(self, "Message received from {}: {}", origin, message);
+        if message == RECALL_MESSAGE && !self.go_home {
+            log!(self, "Recalled by the base station, flying home");
+            self.mission = None;
+            self.go_home = true;
+        }
+        self.send_messages(sender, origin, sequence, message);
+    }
+
+    // Starts a new flood from this drone
+    fn flood_message(&mut self, message: &str) {
+        self.flood_sequence += 1;
+        self.seen_floods
+            .insert((self.id, self.flood_sequence), self.clock.now());
+        self.send_messages(self.id, self.id, self.flood_sequence, message);
+    }
+
+    fn send_messages(&self, sender: usize, origin: usize, sequence: u32, message: &str) {
+        let message = Packet::Message {
+            sender: self.id,
+            origin,
+            sequence,
+            payload: message.to_string(),
+        };
+        self.send_to_neighbors(&message, Some(sender));
+    }
+
+    fn send_to_simulator(&self, packet: &Packet) -> Result<(), DroneError> {
+        self.transport
+            .send(&packet.encode()?, Endpoint::Simulator)?;
+        Ok(())
+    }
+
+    fn send_packet(&self, packet: &Packet, to: usize) -> Result<(), DroneError> {
+        let data = packet.encode()?;
+        self.transport.send(&data, Endpoint::Drone(to))?;
+        self.report_packet(packet, &data, Some(to));
+
+        log!(
+            self,
+            "Sent {} to neighbor {}: {:?}",
+            packet.name(),
+            to,
+            packet
         );
+        Ok(())
+    }
+
+    // A failed send to one neighbor should not stop the packet from reaching the others
+    fn send_to_neighbors(&self, packet: &Packet, except: Option<usize>) {
+        for neighbor in &self.routing_table.neighbors {
+            if Some(neighbor.id) == except {
+                continue;
+            }
+            if let Err(e) = self.send_packet(packet, neighbor.id) {
+                println!("Could not send {} to {}: {}", packet.name(), neighbor.id, e);
+            }
+        }
     }
 
-    fn find_furthest_neighbor_from_target(&self, target: &Coordinate) -> Option<&Neighbor> {
-        self.routing_table.neighbors.iter().max_by(|n1, n2| {
-            let distance1 =
-                self.calculate_distance(n1.position.x, n1.position.y, target.x, target.y);
-            let distance2 =
-                self.calculate_distance(n2.position.x, n2.position.y, target.x, target.y);
-            distance1.partial_cmp(&distance2).unwrap()
-        })
-    }
-
-    fn receive_move_request(&mut self, message: &str) {
-        let message_parts: Vec<&str> = message.trim().split_whitespace().collect();
-
-        if message_parts.len() == 4 {
-            if let (Ok(requester_id), Ok(target_x), Ok(target_y)) = (
-                message_parts[1].parse::<usize>(),
-                message_parts[2].parse::<f32>(),
-                message_parts[3].parse::<f32>(),
-            ) {
-                let mut request_is_neighbor = false;
-                for neighbor in &self.routing_table.neighbors {
-                    if neighbor.id == requester_id {
-                        request_is_neighbor = true;
+    // Unicast that is acknowledged and retransmitted when reliable delivery is enabled
+    fn send_reliable(&mut self, packet: Packet, to: usize) -> Result<(), DroneError> {
+        if !self.config.reliable_delivery {
+            return self.send_packet(&packet, to);
+        }
+
+        let sequence = self.reliable.register(to, &packet);
+        let reliable = Packet::Reliable {
+            sender: self.id,
+            epoch: self.reliable.epoch(),
+            sequence,
+            packet: Box::new(packet),
+        };
+        self.send_packet(&reliable, to)
+    }
+
+    fn receive_reliable(
+        &mut self,
+        sender: usize,
+        epoch: u32,
+        sequence: u32,
+        packet: Packet,
+    ) -> Result<(), DroneError> {
+        let ack = Packet::Ack {
+            sender: self.id,
+            sequence,
+        };
+        self.send_packet(&ack, sender)?;
+
+        if self.reliable.receive(sender, epoch, sequence) {
+            self.handle_packet(packet)
+        } else {
+            log!(
+                self,
+                "Dropping duplicate message {} from {}",
+                sequence,
+                sender
+            );
+            Ok(())
+        }
+    }
+
+    fn retransmit(&mut self) {
+        for timeout in self.reliable.poll() {
+            match timeout {
+                Timeout::Retransmit {
+                    to,
+                    sequence,
+                    packet,
+                } => {
+                    let reliable = Packet::Reliable {
+                        sender: self.id,
+                        epoch: self.reliable.epoch(),
+                        sequence,
+                        packet: Box::new(packet),
+                    };
+                    if let Err(e) = self.send_packet(&reliable, to) {
+                        println!("Could not retransmit to {}: {}", to, e);
                     }
                 }
-
-                if !request_is_neighbor {
-                    println!("Requester is not a neighbor: {}", requester_id);
-                    return;
+                // A next hop that never answers is treated as a broken link
+                Timeout::Failed { to } => {
+                    if self.routing_table.is_neighbor(to) {
+                        self.handle_link_break(to);
+                    }
                 }
+            }
+        }
+    }
 
-                let target = Coordinate {
-                    x: target_x,
-                    y: target_y,
+    fn add_neighbor(&mut self, id: usize, position: Coordinate) -> Result<(), DroneError> {
+        if self.is_within_communication_radius(position.x, position.y) {
+            if !self.routing_table.is_neighbor(id) {
+                log!(self, "Neighbor {} added", id);
+                self.routing_table.add_neighbor(id, position);
+                let neighbor_message = Packet::AddNeighbor {
+                    id: self.id,
+                    position: self.position.clone(),
                 };
-
-                self.move_towards(&target);
+                self.send_packet(&neighbor_message, id)?;
             }
         } else {
-            println!("Invalid move request format.");
+            log!(self, "Neighbor to far away.");
         }
+        Ok(())
     }
 
-    fn is_within_communication_radius(&self, x: f32, y: f32) -> bool {
-        let distance = ((x - self.position.x).powi(2) + (y - self.position.y).powi(2)).sqrt();
-        distance <= COMMUNICATION_RADIUS
-    }
-
-    fn receive_target_from_simulator(&self, message: &str) -> Option<Coordinate> {
-        let message_parts: Vec<&str> = message.trim().split_whitespace().collect();
-        if let (Ok(neighbor_x), Ok(neighbor_y)) = (
-            message_parts[1].parse::<f32>(),
-            message_parts[2].parse::<f32>(),
-        ) {
-            let target = Coordinate {
-                x: neighbor_x,
-                y: neighbor_y,
+    pub fn send_data(&mut self, destination: usize, payload: &str) -> Result<(), DroneError> {
+        if destination == self.id {
+            log!(self, "Data delivered to self: {}", payload);
+            return Ok(());
+        }
+
+        let data = Packet::Data {
+            sender: self.id,
+            origin: self.id,
+            destination,
+            payload: payload.to_string(),
+        };
+        self.send_routed(data, destination)
+    }
+
+    // Sends a packet that starts here along the route to its destination. Without a route
+    // it waits until route discovery has found one.
+    fn send_routed(&mut self, packet: Packet, destination: usize) -> Result<(), DroneError> {
+        if let Some(route) = self.routing_table.route_to(destination) {
+            let next_hop = route.next_hop;
+            self.routing_table.refresh_route(destination);
+            self.send_reliable(packet, next_hop)?;
+        } else {
+            self.pending_data
+                .entry(destination)
+                .or_default()
+                .push(packet);
+            if !self.route_requests.contains_key(&destination) {
+                self.send_route_request(destination);
+            }
+        }
+        Ok(())
+    }
+
+    fn send_route_request(&mut self, destination: usize) {
+        self.sequence_number += 1;
+        self.broadcast_id += 1;
+        self.seen_requests
+            .insert((self.id, self.broadcast_id), self.clock.now());
+        self.route_requests.insert(destination, self.clock.now());
+
+        let destination_sequence = self
+            .routing_table
+            .routes
+            .get(&destination)
+            .map(|route| route.destination_sequence)
+            .unwrap_or(0);
+
+        let request = Packet::RouteRequest {
+            sender: self.id,
+            broadcast_id: self.broadcast_id,
+            origin: self.id,
+            origin_sequence: self.sequence_number,
+            destination,
+            destination_sequence,
+            hop_count: 0,
+        };
+        self.send_to_neighbors(&request, None);
+        log!(self, "Started route discovery for drone {}", destination);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn receive_route_request(
+        &mut self,
+        sender: usize,
+        broadcast_id: u32,
+        origin: usize,
+        origin_sequence: u32,
+        destination: usize,
+        destination_sequence: u32,
+        hop_count: u32,
+    ) -> Result<(), DroneError> {
+        if self
+            .seen_requests
+            .insert((origin, broadcast_id), self.clock.now())
+            .is_some()
+        {
+            log!(
+                self,
+                "Dropping duplicate route request {} from {}",
+                broadcast_id,
+                origin
+            );
+            return Ok(());
+        }
+
+        // Reverse path towards the originator
+        self.routing_table.update_neighbor_route(sender);
+        if origin != sender {
+            self.routing_table
+                .update_route(origin, sender, hop_count + 1, origin_sequence);
+        }
+
+        if destination == self.id {
+            if destination_sequence > self.sequence_number {
+                self.sequence_number = destination_sequence;
+            }
+            let reply = Packet::RouteReply {
+                sender: self.id,
+                origin,
+                destination: self.id,
+                destination_sequence: self.sequence_number,
+                hop_count: 0,
+            };
+            self.send_reliable(reply, sender)?;
+            log!(self, "Replied to route request from {}", origin);
+            return Ok(());
+        }
+
+        let known_route = self
+            .routing_table
+            .route_to(destination)
+            .filter(|route| route.destination_sequence >= destination_sequence)
+            .cloned();
+
+        if let Some(route) = known_route {
+            let reply = Packet::RouteReply {
+                sender: self.id,
+                origin,
+                destination,
+                destination_sequence: route.destination_sequence,
+                hop_count: route.hop_count,
             };
-            println!("Received target from simulator: {:?}", target);
-            Option::from(target)
+            self.routing_table.add_precursor(destination, sender);
+            self.routing_table.add_precursor(origin, route.next_hop);
+            self.send_reliable(reply, sender)?;
+            log!(
+                self,
+                "Replied to route request from {} with known route to {}",
+                origin,
+                destination
+            );
         } else {
-            println!("Error reading coordinate");
-            None
+            let request = Packet::RouteRequest {
+                sender: self.id,
+                broadcast_id,
+                origin,
+                origin_sequence,
+                destination,
+                destination_sequence,
+                hop_count: hop_count + 1,
+            };
+            self.send_to_neighbors(&request, Some(sender));
         }
+        Ok(())
     }
 
-    fn receive_and_send_message(&mut self, message: &str) {
-        let message_parts: Vec<&str> = message.trim().split_whitespace().collect();
-        if let (Ok(sender), Ok(message_sent)) = (
-            message_parts[1].parse::<usize>(),
-            message_parts[2].parse::<String>(),
-        ) {
-            println!("Message received: {}", message_sent);
-            self.go_home = true;
-            self.send_messages(sender, message_sent);
+    fn receive_route_reply(
+        &mut self,
+        sender: usize,
+        origin: usize,
+        destination: usize,
+        destination_sequence: u32,
+        hop_count: u32,
+    ) -> Result<(), DroneError> {
+        self.routing_table.update_neighbor_route(sender);
+        self.routing_table
+            .update_route(destination, sender, hop_count + 1, destination_sequence);
+
+        if origin == self.id {
+            log!(self, "Route to {} found through {}", destination, sender);
+            return self.send_pending_data(destination);
+        }
+
+        if let Some(route) = self.routing_table.route_to(origin) {
+            let next_hop = route.next_hop;
+            let reply = Packet::RouteReply {
+                sender: self.id,
+                origin,
+                destination,
+                destination_sequence,
+                hop_count: hop_count + 1,
+            };
+            self.routing_table.add_precursor(destination, next_hop);
+            self.routing_table.add_precursor(origin, sender);
+            self.send_reliable(reply, next_hop)?;
+        } else {
+            log!(self, "No reverse route to {}, dropping route reply", origin);
         }
+        Ok(())
     }
 
-    fn send_messages(&self, sender: usize, message: String) {
-        for neighbor in &self.routing_table.neighbors {
-            if neighbor.id != sender {
-                self.send_message(&message, neighbor.id, "MESSAGE");
+    fn send_pending_data(&mut self, destination: usize) -> Result<(), DroneError> {
+        self.route_requests.remove(&destination);
+        let pending = self.pending_data.remove(&destination).unwrap_or_default();
+        if let Some(route) = self.routing_table.route_to(destination) {
+            let next_hop = route.next_hop;
+            for data in pending {
+                self.send_reliable(data, next_hop)?;
             }
         }
+        Ok(())
     }
 
-    fn send_message(&self, message: &str, to: usize, message_type: &str) {
-        let message = format!("{} {} {}", message_type, self.id, message);
-        let port = STANDARD_PORT + to as u32;
-        let neighbor_address: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+    fn receive_data(
+        &mut self,
+        sender: usize,
+        origin: usize,
+        destination: usize,
+        payload: String,
+    ) -> Result<(), DroneError> {
+        self.routing_table.update_neighbor_route(sender);
 
-        self.socket
-            .send_to(message.as_bytes(), neighbor_address)
-            .unwrap();
+        if destination == self.id {
+            log!(self, "Data received from {}: {}", origin, payload);
+            return Ok(());
+        }
 
-        println!("Sent message to neighbor {}: {}", to, message);
+        let data = Packet::Data {
+            sender: self.id,
+            origin,
+            destination,
+            payload,
+        };
+        self.forward(data, sender, origin, destination)
     }
 
-    fn add_neighbor(&mut self, message: &str) {
-        let message_parts: Vec<&str> = message.trim().split_whitespace().collect();
-        if let (Ok(id), Ok(neighbor_x), Ok(neighbor_y)) = (
-            message_parts[1].parse::<usize>(),
-            message_parts[2].parse::<f32>(),
-            message_parts[3].parse::<f32>(),
-        ) {
-            let position = Coordinate {
-                x: neighbor_x,
-                y: neighbor_y,
+    // Passes on a packet routed through this drone, or tells the drone it came from that
+    // the route is gone
+    fn forward(
+        &mut self,
+        packet: Packet,
+        sender: usize,
+        origin: usize,
+        destination: usize,
+    ) -> Result<(), DroneError> {
+        if let Some(route) = self.routing_table.route_to(destination) {
+            let next_hop = route.next_hop;
+            self.routing_table.add_precursor(destination, sender);
+            self.routing_table.refresh_route(destination);
+            self.routing_table.refresh_route(origin);
+            self.send_reliable(packet, next_hop)
+        } else {
+            // Tell the upstream drone that this route no longer exists
+            log!(
+                self,
+                "No route to {}, sending route error to {}",
+                destination,
+                sender
+            );
+            let destination_sequence = self
+                .routing_table
+                .routes
+                .get(&destination)
+                .map(|route| route.destination_sequence)
+                .unwrap_or(0);
+            let error = Packet::RouteError {
+                sender: self.id,
+                unreachable: vec![(destination, destination_sequence)],
             };
+            self.send_reliable(error, sender)
+        }
+    }
 
-            if self.is_within_communication_radius(neighbor_x, neighbor_y) {
-                let mut contains = false;
-                for neighbor in &self.routing_table.neighbors {
-                    if neighbor.id == id {
-                        contains = true;
-                    }
-                }
+    fn send_hello(&mut self) {
+        let hello = Packet::Hello {
+            id: self.id,
+            position: self.position.clone(),
+        };
+        let sent = hello.encode().map_err(DroneError::from).and_then(|data| {
+            self.transport.broadcast(&data)?;
+            Ok(data)
+        });
+        match sent {
+            Ok(data) => self.report_packet(&hello, &data, None),
+            Err(e) => println!("Could not send hello: {}", e),
+        }
+        self.last_hello = Some(self.clock.now());
+    }
 
-                if !contains {
-                    println!("Neighbor {} added", id);
-                    self.routing_table.neighbors.push(Neighbor { id, position });
-                    let neighbor_message = format!("{} {}", neighbor_x, neighbor_y);
-                    self.send_message(&neighbor_message, id, "ADD_NEIGHBOR")
-                }
-            } else {
-                println!("Neighbor to far away.");
+    fn receive_hello(&mut self, id: usize, position: Coordinate) {
+        if id == self.id {
+            return;
+        }
+
+        let in_range = self.is_within_communication_radius(position.x, position.y);
+        let known = self
+            .routing_table
+            .neighbors
+            .iter_mut()
+            .find(|neighbor| neighbor.id == id);
+
+        match (known, in_range) {
+            (Some(neighbor), true) => {
+                neighbor.position = position;
+                self.routing_table.update_neighbor_route(id);
             }
+            (Some(_), false) => self.handle_link_break(id),
+            (None, true) => {
+                log!(self, "Neighbor {} discovered", id);
+                self.routing_table.add_neighbor(id, position);
+            }
+            (None, false) => {}
         }
     }
 
-    pub fn run(&mut self) {
-        let mut buffer = [0u8; 1024];
-
-        while !self.go_home {
-            println!("Waiting for request...");
-            if let Ok((size, _)) = self.socket.recv_from(&mut buffer) {
-                let message = std::str::from_utf8(&buffer[..size]).unwrap();
-                let message_parts: Vec<&str> = message.trim().split_whitespace().collect();
-                println!("########### {} #################", message_parts[0]);
-                if message_parts[0] == "MOVE_REQUEST" {
-                    self.receive_move_request(message);
-                } else if message_parts[0] == "ADD_NEIGHBOR" {
-                    self.add_neighbor(message)
-                } else if message_parts[0] == "MESSAGE" {
-                    self.receive_and_send_message(message)
-                } else if message_parts[0] == "UPDATE" {
-                    self.update_neighbor(message);
-                } else if message_parts[0] == "POSITION" {
-                    self.send_position_to_simulator();
-                } else if message_parts[0] == "MOVE" {
-                    let target = self.receive_target_from_simulator(message);
-                    if let Some(target) = target {
-                        self.move_towards(&target);
-                    } else {
-                        println!("Exit program");
-                        break;
-                    }
+    fn hello_due(&self) -> bool {
+        match self.last_hello {
+            Some(last_hello) => {
+                self.clock.now().duration_since(last_hello) >= self.config.hello_interval
+            }
+            None => true,
+        }
+    }
+
+    // The simulator sends the neighbor configuration once it knows the drone is listening
+    fn register(&mut self) {
+        let register = Packet::Register {
+            id: self.id,
+            position: self.position.clone(),
+        };
+        if let Err(e) = self.send_to_simulator(&register) {
+            println!("Could not register with simulator: {}", e);
+        }
+        self.last_register = Some(self.clock.now());
+    }
+
+    fn register_due(&self) -> bool {
+        match self.last_register {
+            _ if self.registered => false,
+            Some(last_register) => {
+                self.clock.now().duration_since(last_register) >= REGISTER_INTERVAL
+            }
+            None => true,
+        }
+    }
+
+    fn on_timer(&mut self) {
+        if self.register_due() {
+            self.register();
+        }
+        if self.hello_due() {
+            self.send_hello();
+        }
+        self.retransmit();
+        self.resend_report();
+        if self.go_home && !self.is_base_station() {
+            if let Err(e) = self.fly_home() {
+                println!("Could not fly home: {}", e);
+            }
+        }
+
+        for neighbor in self
+            .routing_table
+            .expired_neighbors(self.config.neighbor_timeout())
+        {
+            log!(self, "Neighbor {} timed out", neighbor);
+            self.handle_link_break(neighbor);
+        }
+        self.routing_table.expire_routes();
+
+        let now = self.clock.now();
+        let delete_period = self.config.delete_period();
+        self.seen_requests
+            .retain(|_, received| now.duration_since(*received) < delete_period);
+        self.seen_floods
+            .retain(|_, received| now.duration_since(*received) < delete_period);
+
+        let discovery_timeout = self.config.active_route_timeout;
+        let failed: Vec<usize> = self
+            .route_requests
+            .iter()
+            .filter(|(_, started)| now.duration_since(**started) > discovery_timeout)
+            .map(|(destination, _)| *destination)
+            .collect();
+        for destination in failed {
+            log!(self, "Route discovery for drone {} failed", destination);
+            self.route_requests.remove(&destination);
+            self.pending_data.remove(&destination);
+        }
+    }
+
+    fn handle_packet(&mut self, packet: Packet) -> Result<(), DroneError> {
+        if let Packet::Hello { id, position } = packet {
+            self.receive_hello(id, position);
+            return Ok(());
+        }
+        log!(self, "########### {} #################", packet.name());
+
+        match packet {
+            Packet::Reliable {
+                sender,
+                epoch,
+                sequence,
+                packet,
+            } => self.receive_reliable(sender, epoch, sequence, *packet),
+            Packet::Ack { sender, sequence } => {
+                self.reliable.acknowledge(sender, sequence);
+                Ok(())
+            }
+            Packet::MoveRequest { sender, .. } => {
+                log!(self, "Ignoring legacy move request from {}", sender);
+                Ok(())
+            }
+            Packet::AddNeighbor { id, position } => self.add_neighbor(id, position),
+            Packet::Message {
+                sender,
+                origin,
+                sequence,
+                payload,
+            } => {
+                self.receive_and_send_message(sender, origin, sequence, &payload);
+                Ok(())
+            }
+            Packet::Update { id, position } => {
+                self.update_neighbor(id, position);
+                Ok(())
+            }
+            Packet::RouteRequest {
+                sender,
+                broadcast_id,
+                origin,
+                origin_sequence,
+                destination,
+                destination_sequence,
+                hop_count,
+            } => self.receive_route_request(
+                sender,
+                broadcast_id,
+                origin,
+                origin_sequence,
+                destination,
+                destination_sequence,
+                hop_count,
+            ),
+            Packet::RouteReply {
+                sender,
+                origin,
+                destination,
+                destination_sequence,
+                hop_count,
+            } => self.receive_route_reply(
+                sender,
+                origin,
+                destination,
+                destination_sequence,
+                hop_count,
+            ),
+            Packet::RouteError {
+                sender,
+                unreachable,
+            } => {
+                self.receive_route_error(sender, &unreachable);
+                Ok(())
+            }
+            Packet::Data {
+                sender,
+                origin,
+                destination,
+                payload,
+            } => self.receive_data(sender, origin, destination, payload),
+            Packet::Send {
+                destination,
+                payload,
+            } => self.send_data(destination, &payload),
+            Packet::RequestPosition => self.send_position_to_simulator(),
+            Packet::RoutingTableRequest => self.send_routing_table_to_simulator(),
+            Packet::Shutdown => {
+                log!(self, "Removed by simulator, shutting down");
+                self.shutdown = true;
+                Ok(())
+            }
+            Packet::RegisterAck => {
+                log!(self, "Registered with simulator");
+                self.registered = true;
+                Ok(())
+            }
+            Packet::Move { target } => {
+                log!(self, "Received target from simulator: {:?}", target);
+                if self.mission.take().is_some() {
+                    log!(self, "Mission cancelled by move");
                 }
+                self.move_towards(&target)
+            }
+            Packet::Mission(_) if self.is_base_station() || self.go_home => {
+                log!(self, "Not taking a mission now");
+                Ok(())
             }
+            Packet::Mission(mission) => {
+                let state = MissionState::new(&mission, &self.position);
+                log!(
+                    self,
+                    "Received {:?} mission with {} waypoints",
+                    mission.pattern,
+                    state.waypoints()
+                );
+                self.mission = Some(state);
+                Ok(())
+            }
+            Packet::MissionStep => self.mission_step(),
+            Packet::ThermalFrame { .. } if self.is_base_station() => Ok(()),
+            Packet::ThermalFrame { sources } => self.scan(&sources),
+            Packet::SearchReport {
+                sender,
+                destination,
+                report,
+            } => self.receive_search_report(sender, destination, report),
+            Packet::SearchAck {
+                sender,
+                origin,
+                destination,
+            } => self.receive_search_ack(sender, origin, destination),
+            Packet::CancelMission => {
+                log!(self, "Mission cancelled");
+                self.mission = None;
+                Ok(())
+            }
+            other => Err(DroneError::UnexpectedPacket(other.name())),
+        }
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<(), DroneError> {
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        // The timeout only wakes the loop up for the timers
+        let Some(size) = self.transport.recv(&mut buffer, timeout)? else {
+            return Ok(());
+        };
+        let packet = Packet::decode(&buffer[..size])?;
+        self.handle_packet(packet)
+    }
+
+    // Runs the timers and handles at most one packet, waiting up to `timeout` for it.
+    // Returns false once the drone has flown home and landed, or has been shut down.
+    pub fn step(&mut self, timeout: Duration) -> bool {
+        if self.landed || self.shutdown {
+            return false;
         }
+        self.on_timer();
 
-        if self.go_home {
-            self.send_finished_to_simulator();
-            self.send_messages(self.id, String::from("message"));
+        if let Err(e) = self.receive(timeout) {
+            self.bad_packets += 1;
+            println!("Dropping packet ({} so far): {}", self.bad_packets, e);
         }
+        if self.shutdown {
+            return false;
+        }
+        self.report_neighbors();
+        !self.landed
+    }
+
+    pub fn run(&mut self) {
+        while self.step(TIMER_INTERVAL) {}
     }
 }
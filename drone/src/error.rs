@@ -0,0 +1,52 @@
+use mesh_proto::{DecodeError, EncodeError};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum DroneError {
+    Io(io::Error),
+    Decode(DecodeError),
+    Encode(EncodeError),
+    UnexpectedPacket(&'static str),
+}
+
+impl fmt::Display for DroneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DroneError::Io(e) => write!(f, "network error: {}", e),
+            DroneError::Decode(e) => write!(f, "malformed packet: {}", e),
+            DroneError::Encode(e) => write!(f, "cannot send packet: {}", e),
+            DroneError::UnexpectedPacket(name) => write!(f, "unexpected {} packet", name),
+        }
+    }
+}
+
+impl Error for DroneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DroneError::Io(e) => Some(e),
+            DroneError::Decode(e) => Some(e),
+            DroneError::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DroneError {
+    fn from(e: io::Error) -> Self {
+        DroneError::Io(e)
+    }
+}
+
+impl From<EncodeError> for DroneError {
+    fn from(e: EncodeError) -> Self {
+        DroneError::Encode(e)
+    }
+}
+
+impl From<DecodeError> for DroneError {
+    fn from(e: DecodeError) -> Self {
+        DroneError::Decode(e)
+    }
+}
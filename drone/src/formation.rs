@@ -0,0 +1,246 @@
+use mesh_proto::geometry::distance;
+use mesh_proto::Coordinate;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::config::DroneConfig;
+use crate::routing::Neighbor;
+
+// Keeps the swarm connected while its drones advance. Each drone only has to hold on to
+// the links of the relative neighborhood graph among itself and its neighbors: a link can
+// be let go when some other neighbor is closer to both ends than they are to each other,
+// since the swarm stays connected through that neighbor. It has to be closer by the safety
+// margin, so that both ends agree on which links to keep even when the positions they
+// know of each other are a step out of date. That graph is connected whenever the mesh
+// is, so no drone can wander off as long as every drone keeps its own links.
+pub struct Formation {
+    // How far a held link may stretch, the radio range less the safety margin
+    reach: f32,
+    margin: f32,
+    // How long a link is held after its neighbor was last heard from
+    memory: Duration,
+    // The links being held, with where and when the neighbor was last heard from. Links at
+    // the edge of the range drop HELLOs, so a neighbor that times out is still held on to
+    // for a while and the drone stays within reach of where it was.
+    links: BTreeMap<usize, (Coordinate, Instant)>,
+}
+
+impl Formation {
+    pub fn new(config: &DroneConfig) -> Self {
+        Formation {
+            reach: (config.communication_radius - config.safety_margin).max(0.0),
+            margin: config.safety_margin,
+            memory: config.delete_period(),
+            links: BTreeMap::new(),
+        }
+    }
+
+    // Shortens or turns a step so the drone stays within reach of the neighbors whose
+    // links have to hold. A drone that is already too far from one of them closes the gap
+    // first.
+    pub fn constrain(
+        &mut self,
+        position: &Coordinate,
+        step: (f32, f32),
+        neighbors: &[Neighbor],
+        now: Instant,
+    ) -> (f32, f32) {
+        self.update_links(position, neighbors, now);
+        let critical: Vec<Coordinate> = self
+            .links
+            .values()
+            .map(|(position, _)| position.clone())
+            .collect();
+        let length = step.0.hypot(step.1);
+        if critical.is_empty() || length == 0.0 {
+            return step;
+        }
+
+        let stretched = critical
+            .iter()
+            .map(|neighbor| (neighbor, distance(position, neighbor)))
+            .filter(|(_, gap)| *gap > self.reach)
+            .max_by(|(_, gap1), (_, gap2)| gap1.total_cmp(gap2));
+        if let Some((neighbor, gap)) = stretched {
+            let closing = (gap - self.reach).min(length) / gap;
+            return (
+                (neighbor.x - position.x) * closing,
+                (neighbor.y - position.y) * closing,
+            );
+        }
+
+        let (fraction, blocking) = self.allowed_fraction(position, step, &critical);
+        let Some(blocking) = blocking else {
+            return step;
+        };
+        let shortened = (step.0 * fraction, step.1 * fraction);
+
+        // Slide along the edge of the blocking neighbor's reach, keeping only the part of the
+        // step that does not pull away from it
+        let gap = distance(position, blocking);
+        if gap == 0.0 {
+            return shortened;
+        }
+        let away = (
+            (position.x - blocking.x) / gap,
+            (position.y - blocking.y) / gap,
+        );
+        let outward = step.0 * away.0 + step.1 * away.1;
+        let tangent = (step.0 - outward * away.0, step.1 - outward * away.1);
+        let (tangent_fraction, _) = self.allowed_fraction(position, tangent, &critical);
+        let slide = (tangent.0 * tangent_fraction, tangent.1 * tangent_fraction);
+
+        // Whichever gets further along the wanted step
+        let progress = |(x, y): (f32, f32)| x * step.0 + y * step.1;
+        if progress(slide) > progress(shortened) {
+            slide
+        } else {
+            shortened
+        }
+    }
+
+    // Picks the links to hold among the current neighbors and the held links whose
+    // neighbors have gone quiet only recently
+    fn update_links(&mut self, position: &Coordinate, neighbors: &[Neighbor], now: Instant) {
+        let mut candidates: BTreeMap<usize, (Coordinate, Instant)> = self
+            .links
+            .iter()
+            .filter(|(_, (_, last_seen))| now.saturating_duration_since(*last_seen) < self.memory)
+            .map(|(id, link)| (*id, link.clone()))
+            .collect();
+        for neighbor in neighbors {
+            candidates.insert(neighbor.id, (neighbor.position.clone(), neighbor.last_seen));
+        }
+
+        self.links = candidates
+            .iter()
+            .filter(|(id, (neighbor, _))| {
+                let length = distance(position, neighbor) - self.margin;
+                !candidates.iter().any(|(other_id, (other, _))| {
+                    other_id != *id
+                        && distance(position, other) < length
+                        && distance(neighbor, other) < length
+                })
+            })
+            .map(|(id, link)| (*id, link.clone()))
+            .collect();
+    }
+
+    // How much of the step can be taken before some critical neighbor is out of reach, and
+    // which neighbor that is
+    fn allowed_fraction<'a>(
+        &self,
+        position: &Coordinate,
+        step: (f32, f32),
+        critical: &'a [Coordinate],
+    ) -> (f32, Option<&'a Coordinate>) {
+        let a = step.0 * step.0 + step.1 * step.1;
+        if a == 0.0 {
+            return (1.0, None);
+        }
+        let mut allowed = 1.0;
+        let mut blocking = None;
+        for neighbor in critical {
+            let offset = (position.x - neighbor.x, position.y - neighbor.y);
+            let b = 2.0 * (offset.0 * step.0 + offset.1 * step.1);
+            let c = offset.0 * offset.0 + offset.1 * offset.1 - self.reach * self.reach;
+            let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+            let fraction = ((-b + root) / (2.0 * a)).max(0.0);
+            if fraction < allowed {
+                allowed = fraction;
+                blocking = Some(neighbor);
+            }
+        }
+        (allowed, blocking)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbor(id: usize, x: f32, y: f32, last_seen: Instant) -> Neighbor {
+        Neighbor {
+            id,
+            position: Coordinate { x, y },
+            last_seen,
+        }
+    }
+
+    // Radio range 150 less the default margin of 15
+    fn formation() -> Formation {
+        Formation::new(&DroneConfig::default())
+    }
+
+    fn after(position: &Coordinate, step: (f32, f32)) -> Coordinate {
+        Coordinate {
+            x: position.x + step.0,
+            y: position.y + step.1,
+        }
+    }
+
+    #[test]
+    fn free_step_is_unchanged() {
+        let now = Instant::now();
+        let position = Coordinate { x: 0.0, y: 0.0 };
+        let neighbors = [neighbor(1, 50.0, 0.0, now)];
+        let step = formation().constrain(&position, (-5.0, 0.0), &neighbors, now);
+        assert_eq!(step, (-5.0, 0.0));
+    }
+
+    #[test]
+    fn step_pulling_straight_away_is_shortened() {
+        let now = Instant::now();
+        let position = Coordinate { x: 0.0, y: 0.0 };
+        let neighbors = [neighbor(1, 100.0, 0.0, now)];
+        let step = formation().constrain(&position, (-50.0, 0.0), &neighbors, now);
+        assert!((step.0 + 35.0).abs() < 1e-3, "{:?}", step);
+        assert!(step.1.abs() < 1e-3, "{:?}", step);
+    }
+
+    #[test]
+    fn step_pulling_sideways_away_slides_along_the_edge() {
+        let now = Instant::now();
+        let position = Coordinate { x: 0.0, y: 0.0 };
+        let neighbors = [neighbor(1, 130.0, 0.0, now)];
+        let step = formation().constrain(&position, (-30.0, 40.0), &neighbors, now);
+        assert!(step.0.abs() < 1e-3, "{:?}", step);
+        assert!(step.1 > 30.0, "{:?}", step);
+        let gap = distance(&after(&position, step), &neighbors[0].position);
+        assert!(gap <= 135.0 + 1e-3, "{}", gap);
+    }
+
+    #[test]
+    fn drone_too_far_closes_the_gap() {
+        let now = Instant::now();
+        let position = Coordinate { x: 0.0, y: 0.0 };
+        let neighbors = [neighbor(1, 200.0, 0.0, now)];
+        let step = formation().constrain(&position, (0.0, 5.0), &neighbors, now);
+        assert!((step.0 - 5.0).abs() < 1e-3, "{:?}", step);
+        assert!(step.1.abs() < 1e-3, "{:?}", step);
+    }
+
+    #[test]
+    fn link_through_a_closer_neighbor_is_let_go() {
+        let now = Instant::now();
+        let position = Coordinate { x: 0.0, y: 0.0 };
+        let neighbors = [neighbor(1, 130.0, 0.0, now), neighbor(2, 65.0, 10.0, now)];
+        let step = formation().constrain(&position, (-30.0, 0.0), &neighbors, now);
+        assert_eq!(step, (-30.0, 0.0));
+    }
+
+    #[test]
+    fn lost_neighbor_is_held_for_a_while() {
+        let now = Instant::now();
+        let position = Coordinate { x: 0.0, y: 0.0 };
+        let mut formation = formation();
+        formation.constrain(&position, (0.0, 0.0), &[neighbor(1, 130.0, 0.0, now)], now);
+
+        let step = formation.constrain(&position, (-30.0, 0.0), &[], now);
+        assert!((step.0 + 5.0).abs() < 1e-3, "{:?}", step);
+
+        let later = now + DroneConfig::default().delete_period();
+        let step = formation.constrain(&position, (-30.0, 0.0), &[], later);
+        assert_eq!(step, (-30.0, 0.0));
+    }
+}
@@ -0,0 +1,16 @@
+pub mod clock;
+pub mod config;
+pub mod drone;
+pub mod error;
+mod formation;
+pub mod mission;
+mod reliable;
+mod routing;
+pub mod sensor;
+pub mod transport;
+
+pub use clock::{Clock, SimClock, SystemClock};
+pub use config::DroneConfig;
+pub use drone::Drone;
+pub use error::DroneError;
+pub use transport::{Endpoint, MemoryNetwork, MemoryTransport, Transport, UdpTransport};
@@ -1,10 +1,10 @@
-mod drone;
-
 use std::env;
 use std::io;
-use std::net::SocketAddr;
 
-use drone::{Coordinate, Drone};
+use drone::config::read_env;
+use drone::{Drone, DroneConfig, UdpTransport};
+use mesh_proto::addressing::{relay_address, simulator_address, MAX_DRONES};
+use mesh_proto::Coordinate;
 
 fn main() -> io::Result<()> {
     // Collect command-line arguments
@@ -18,6 +18,10 @@ fn main() -> io::Result<()> {
 
     // Parse command-line arguments
     let id: usize = args[1].parse().expect("Could not parse drone ID");
+    if id >= MAX_DRONES {
+        eprintln!("Drone ID must be less than {}", MAX_DRONES);
+        std::process::exit(1);
+    }
     let x: f32 = args[2]
         .parse()
         .expect("Could not parse initial position (x)");
@@ -25,12 +29,19 @@ fn main() -> io::Result<()> {
         .parse()
         .expect("Could not parse initial position (y)");
 
-    // Address of the simulator program
-    let simulator_address: SocketAddr = "127.0.0.1:7878".parse().unwrap();
-
     let position = Coordinate { x, y };
 
-    let mut drone = Drone::new(id, position, simulator_address, false)?;
+    let mut transport = UdpTransport::bind(id, simulator_address())?;
+    // Set by the simulator for the drones it launches
+    if read_env("DRONE_RELAY").unwrap_or(false) {
+        transport = transport.with_relay(relay_address());
+    }
+    // A drone program logs what it does unless DRONE_VERBOSE=false
+    let config = DroneConfig {
+        verbose: read_env("DRONE_VERBOSE").unwrap_or(true),
+        ..DroneConfig::from_env()
+    };
+    let mut drone = Drone::new(id, position, transport, false, config);
 
     drone.run();
 
@@ -0,0 +1,355 @@
+use mesh_proto::geometry::{bounds, centroid, contains, distance, row_crossings, segment_inside};
+use mesh_proto::{Coordinate, Mission, Pattern};
+use std::f32::consts::PI;
+
+// Waypoints closer than this are flown as one
+const SAME_POINT: f32 = 1e-3;
+
+// Where a drone is on its coverage path
+pub struct MissionState {
+    waypoints: Vec<Coordinate>,
+    next: usize,
+}
+
+impl MissionState {
+    pub fn new(mission: &Mission, start: &Coordinate) -> Self {
+        MissionState {
+            waypoints: coverage_path(mission, start),
+            next: 0,
+        }
+    }
+
+    pub fn target(&self) -> Option<&Coordinate> {
+        self.waypoints.get(self.next)
+    }
+
+    // The waypoints still ahead, starting with the target
+    pub fn upcoming(&self) -> &[Coordinate] {
+        &self.waypoints[self.next.min(self.waypoints.len())..]
+    }
+
+    pub fn advance(&mut self) {
+        self.next += 1;
+    }
+
+    pub fn waypoints(&self) -> usize {
+        self.waypoints.len()
+    }
+}
+
+// Waypoints that fly over the mission area with the mission's pattern, starting from the
+// end closest to where the drone is
+pub fn coverage_path(mission: &Mission, start: &Coordinate) -> Vec<Coordinate> {
+    if mission.area.len() < 3 || mission.spacing <= 0.0 {
+        return Vec::new();
+    }
+    let path = match mission.pattern {
+        Pattern::Lawnmower => lawnmower(&mission.area, mission.spacing, start),
+        Pattern::ExpandingSquare => expanding_square(&mission.area, mission.spacing),
+        Pattern::Sector => sector(&mission.area, mission.spacing),
+    };
+    clip_path(mission, &path)
+}
+
+// Keeps the waypoints inside the area and out of the obstacles. A leg that leaves the area
+// or enters an obstacle is cut where it crosses the edge, and the drone flies straight on
+// to where the path comes back.
+fn clip_path(mission: &Mission, path: &[Coordinate]) -> Vec<Coordinate> {
+    let legs: Vec<(&Coordinate, &Coordinate)> = match path {
+        [only] => vec![(only, only)],
+        _ => path.windows(2).map(|leg| (&leg[0], &leg[1])).collect(),
+    };
+    let mut clipped: Vec<Coordinate> = Vec::new();
+    for (from, to) in legs {
+        for (from, to) in segment_inside(&mission.area, from, to) {
+            for (from, to) in outside_obstacles(&mission.obstacles, from, to) {
+                for point in [from, to] {
+                    if clipped
+                        .last()
+                        .is_none_or(|last| distance(last, &point) > SAME_POINT)
+                    {
+                        clipped.push(point);
+                    }
+                }
+            }
+        }
+    }
+    clipped
+}
+
+// The parts of a leg outside every obstacle
+fn outside_obstacles(
+    obstacles: &[Vec<Coordinate>],
+    from: Coordinate,
+    to: Coordinate,
+) -> Vec<(Coordinate, Coordinate)> {
+    if distance(&from, &to) <= SAME_POINT {
+        // A single waypoint
+        if obstacles.iter().any(|obstacle| contains(obstacle, &from)) {
+            return Vec::new();
+        }
+        return vec![(from, to)];
+    }
+    let mut parts = vec![(from, to)];
+    for obstacle in obstacles {
+        let mut outside = Vec::new();
+        for (from, to) in parts {
+            let mut cursor = from;
+            for (enter, leave) in segment_inside(obstacle, &cursor, &to) {
+                if distance(&cursor, &enter) > SAME_POINT {
+                    outside.push((cursor, enter));
+                }
+                cursor = leave;
+            }
+            if distance(&cursor, &to) > SAME_POINT {
+                outside.push((cursor, to));
+            }
+        }
+        parts = outside;
+    }
+    parts
+}
+
+// Boustrophedon rows across the polygon, one spacing apart and half a spacing in from
+// the edges. A row that crosses a concave polygon is split into the parts inside it.
+fn lawnmower(area: &[Coordinate], spacing: f32, start: &Coordinate) -> Vec<Coordinate> {
+    let (min, max) = bounds(area);
+    let mut rows = Vec::new();
+    let mut y = min.y + spacing / 2.0;
+    if y > max.y {
+        y = (min.y + max.y) / 2.0;
+    }
+    while y <= max.y {
+        let crossings = row_crossings(area, y);
+        let segments: Vec<(f32, f32)> = crossings
+            .chunks_exact(2)
+            .map(|pair| inset(pair[0], pair[1], spacing))
+            .collect();
+        if !segments.is_empty() {
+            rows.push((y, segments));
+        }
+        y += spacing;
+    }
+
+    if (start.y - max.y).abs() < (start.y - min.y).abs() {
+        rows.reverse();
+    }
+    let mut rightwards = match rows.first() {
+        Some((_, segments)) => {
+            let left = segments[0].0;
+            let right = segments[segments.len() - 1].1;
+            (start.x - left).abs() <= (start.x - right).abs()
+        }
+        None => true,
+    };
+
+    let mut waypoints = Vec::new();
+    for (y, mut segments) in rows {
+        if !rightwards {
+            segments.reverse();
+        }
+        for (left, right) in segments {
+            let (from, to) = if rightwards {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            waypoints.push(Coordinate { x: from, y });
+            if to != from {
+                waypoints.push(Coordinate { x: to, y });
+            }
+        }
+        rightwards = !rightwards;
+    }
+    waypoints
+}
+
+// Keeps the drone half a spacing inside the edges, or flies over the middle of a part
+// that is narrower than that
+fn inset(left: f32, right: f32, spacing: f32) -> (f32, f32) {
+    if right - left > spacing {
+        (left + spacing / 2.0, right - spacing / 2.0)
+    } else {
+        let middle = (left + right) / 2.0;
+        (middle, middle)
+    }
+}
+
+// Square spiral out from the middle of the area, with legs of 1, 1, 2, 2, 3, 3, ...
+// spacings, until it has grown past the farthest corner. The outer legs go past the edge
+// and are clipped to the area.
+fn expanding_square(area: &[Coordinate], spacing: f32) -> Vec<Coordinate> {
+    let center = centroid(area);
+    let reach = farthest_corner(area, &center);
+    let directions = [(0.0, 1.0), (1.0, 0.0), (0.0, -1.0), (-1.0, 0.0)];
+
+    let mut position = center.clone();
+    let mut waypoints = vec![center];
+    let mut leg = 0;
+    loop {
+        let length = (leg / 2 + 1) as f32 * spacing;
+        if length > 2.0 * reach + spacing {
+            break;
+        }
+        let (dx, dy) = directions[leg % directions.len()];
+        position = Coordinate {
+            x: position.x + dx * length,
+            y: position.y + dy * length,
+        };
+        waypoints.push(position.clone());
+        leg += 1;
+    }
+    waypoints
+}
+
+// Wedges out from the middle to the farthest corner and back. There are enough wedges
+// that the outer ends are at most two spacings apart. The wedges reach past the edge
+// everywhere but the farthest corner and are clipped to the area.
+fn sector(area: &[Coordinate], spacing: f32) -> Vec<Coordinate> {
+    let center = centroid(area);
+    let reach = farthest_corner(area, &center);
+    let wedges = ((PI * reach / spacing).ceil() as usize).max(3);
+    let angle = 2.0 * PI / wedges as f32;
+    let tip = |i: usize| Coordinate {
+        x: center.x + reach * (angle * i as f32).cos(),
+        y: center.y + reach * (angle * i as f32).sin(),
+    };
+
+    let mut waypoints = vec![center.clone()];
+    for i in 0..wedges {
+        waypoints.push(tip(i));
+        waypoints.push(tip(i + 1));
+        waypoints.push(center.clone());
+    }
+    waypoints
+}
+
+fn farthest_corner(area: &[Coordinate], from: &Coordinate) -> f32 {
+    area.iter()
+        .map(|corner| distance(corner, from))
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(corners: &[(f32, f32)]) -> Vec<Coordinate> {
+        corners.iter().map(|&(x, y)| Coordinate { x, y }).collect()
+    }
+
+    fn square() -> Vec<Coordinate> {
+        polygon(&[(0.0, 0.0), (200.0, 0.0), (200.0, 200.0), (0.0, 200.0)])
+    }
+
+    fn triangle() -> Vec<Coordinate> {
+        polygon(&[(0.0, 0.0), (300.0, 0.0), (50.0, 120.0)])
+    }
+
+    fn l_shape() -> Vec<Coordinate> {
+        polygon(&[
+            (0.0, 0.0),
+            (300.0, 0.0),
+            (300.0, 100.0),
+            (100.0, 100.0),
+            (100.0, 300.0),
+            (0.0, 300.0),
+        ])
+    }
+
+    // Clipped waypoints are on the edge, which the even-odd rule may count either way
+    fn inside(area: &[Coordinate], point: &Coordinate) -> bool {
+        contains(area, point)
+            || area
+                .iter()
+                .zip(area.iter().cycle().skip(1))
+                .any(|(a, b)| distance(a, point) + distance(point, b) - distance(a, b) < 1e-2)
+    }
+
+    fn mission(area: Vec<Coordinate>, pattern: Pattern) -> Mission {
+        Mission {
+            area,
+            pattern,
+            spacing: 20.0,
+            obstacles: Vec::new(),
+        }
+    }
+
+    fn assert_inside(pattern: Pattern) {
+        for area in [square(), triangle(), l_shape()] {
+            let mission = mission(area.clone(), pattern);
+            let path = coverage_path(&mission, &Coordinate { x: -50.0, y: -50.0 });
+            assert!(!path.is_empty(), "{:?} on {:?}", pattern, area);
+            for waypoint in &path {
+                assert!(
+                    inside(&area, waypoint),
+                    "{:?} on {:?} leaves the area at {:?}",
+                    pattern,
+                    area,
+                    waypoint
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lawnmower_stays_inside_the_area() {
+        assert_inside(Pattern::Lawnmower);
+    }
+
+    #[test]
+    fn expanding_square_stays_inside_the_area() {
+        assert_inside(Pattern::ExpandingSquare);
+    }
+
+    #[test]
+    fn sector_stays_inside_the_area() {
+        assert_inside(Pattern::Sector);
+    }
+
+    #[test]
+    fn clipped_leg_ends_on_the_edge() {
+        let path = clip_path(
+            &mission(square(), Pattern::Sector),
+            &polygon(&[
+                (100.0, 100.0),
+                (100.0, 300.0),
+                (300.0, 300.0),
+                (150.0, 100.0),
+            ]),
+        );
+        assert_eq!(path.len(), 4);
+        assert!(distance(&path[1], &Coordinate { x: 100.0, y: 200.0 }) < 1e-3);
+        assert!(distance(&path[3], &Coordinate { x: 150.0, y: 100.0 }) < 1e-3);
+    }
+
+    #[test]
+    fn no_waypoints_in_obstacles() {
+        let obstacle = polygon(&[(60.0, 60.0), (140.0, 60.0), (140.0, 140.0), (60.0, 140.0)]);
+        for pattern in [
+            Pattern::Lawnmower,
+            Pattern::ExpandingSquare,
+            Pattern::Sector,
+        ] {
+            let mission = Mission {
+                obstacles: vec![obstacle.clone()],
+                ..mission(square(), pattern)
+            };
+            let path = coverage_path(&mission, &Coordinate { x: 0.0, y: 0.0 });
+            assert!(!path.is_empty());
+            for waypoint in &path {
+                assert!(inside(&square(), waypoint));
+                // On the edge of the obstacle is fine, that is where the leg was cut
+                let strictly_inside = waypoint.x > 60.01
+                    && waypoint.x < 139.99
+                    && waypoint.y > 60.01
+                    && waypoint.y < 139.99;
+                assert!(
+                    !strictly_inside,
+                    "{:?} puts {:?} in the obstacle",
+                    pattern, waypoint
+                );
+            }
+        }
+    }
+}
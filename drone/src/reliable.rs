@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use mesh_proto::Packet;
+
+use crate::clock::Clock;
+
+// How many sequence numbers are remembered per peer to drop duplicates
+const RECEIVE_WINDOW: usize = 256;
+
+struct PendingMessage {
+    packet: Packet,
+    attempts: u32,
+    timeout: Duration,
+    next_attempt: Instant,
+}
+
+type FailureCallback = Box<dyn FnMut(usize, &Packet)>;
+
+pub enum Timeout {
+    Retransmit {
+        to: usize,
+        sequence: u32,
+        packet: Packet,
+    },
+    Failed {
+        to: usize,
+    },
+}
+
+// Per-peer sequence numbers, acknowledgements and retransmission on top of plain UDP.
+// Reliable packets are wrapped in Packet::Reliable and answered with Packet::Ack.
+// Sequence numbers start over when a drone restarts, so packets also carry the epoch the
+// sender was started in, and a peer's received sequence numbers are forgotten when its
+// epoch changes.
+pub struct ReliableChannel {
+    epoch: u32,
+    next_sequence: HashMap<usize, u32>,
+    pending: BTreeMap<(usize, u32), PendingMessage>,
+    // The epoch each peer was last heard from in, and what was received from it since
+    received: HashMap<usize, (u32, BTreeSet<u32>)>,
+    initial_timeout: Duration,
+    max_retries: u32,
+    on_failure: FailureCallback,
+    clock: Rc<dyn Clock>,
+}
+
+impl ReliableChannel {
+    pub fn new(
+        initial_timeout: Duration,
+        max_retries: u32,
+        clock: Rc<dyn Clock>,
+        on_failure: impl FnMut(usize, &Packet) + 'static,
+    ) -> Self {
+        ReliableChannel {
+            epoch: clock.boot_epoch(),
+            next_sequence: HashMap::new(),
+            pending: BTreeMap::new(),
+            received: HashMap::new(),
+            initial_timeout,
+            max_retries,
+            on_failure: Box::new(on_failure),
+            clock,
+        }
+    }
+
+    // Registers the packet for retransmission and returns the sequence number to send it with
+    pub fn register(&mut self, to: usize, packet: &Packet) -> u32 {
+        let sequence = self.next_sequence.entry(to).or_insert(0);
+        *sequence += 1;
+
+        self.pending.insert(
+            (to, *sequence),
+            PendingMessage {
+                packet: packet.clone(),
+                attempts: 1,
+                timeout: self.initial_timeout,
+                next_attempt: self.clock.now() + self.initial_timeout,
+            },
+        );
+        *sequence
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    pub fn acknowledge(&mut self, from: usize, sequence: u32) {
+        self.pending.remove(&(from, sequence));
+    }
+
+    // Returns false if the packet has already been received from this peer
+    pub fn receive(&mut self, from: usize, epoch: u32, sequence: u32) -> bool {
+        let (last_epoch, received) = self
+            .received
+            .entry(from)
+            .or_insert_with(|| (epoch, BTreeSet::new()));
+        if *last_epoch != epoch {
+            *last_epoch = epoch;
+            received.clear();
+        }
+        if received.len() >= RECEIVE_WINDOW {
+            if let Some(&oldest) = received.iter().next() {
+                if sequence <= oldest {
+                    return false;
+                }
+                received.remove(&oldest);
+            }
+        }
+        received.insert(sequence)
+    }
+
+    // Collects the packets that timed out, doubling the timeout for every retransmission
+    // and giving up once the retry limit is reached.
+    pub fn poll(&mut self) -> Vec<Timeout> {
+        let now = self.clock.now();
+        let mut timeouts = Vec::new();
+        let mut failed = Vec::new();
+
+        for (&(to, sequence), pending) in self.pending.iter_mut() {
+            if pending.next_attempt > now {
+                continue;
+            }
+
+            if pending.attempts > self.max_retries {
+                failed.push((to, sequence));
+                continue;
+            }
+
+            pending.attempts += 1;
+            pending.timeout *= 2;
+            pending.next_attempt = now + pending.timeout;
+            timeouts.push(Timeout::Retransmit {
+                to,
+                sequence,
+                packet: pending.packet.clone(),
+            });
+        }
+
+        for key in failed {
+            if let Some(pending) = self.pending.remove(&key) {
+                (self.on_failure)(key.0, &pending.packet);
+                timeouts.push(Timeout::Failed { to: key.0 });
+            }
+        }
+        timeouts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SimClock;
+
+    fn channel(clock: &SimClock) -> ReliableChannel {
+        ReliableChannel::new(
+            Duration::from_millis(100),
+            2,
+            Rc::new(clock.clone()),
+            |_, _| {},
+        )
+    }
+
+    #[test]
+    fn duplicate_is_dropped() {
+        let mut channel = channel(&SimClock::new());
+        assert!(channel.receive(1, 0, 1));
+        assert!(channel.receive(1, 0, 2));
+        assert!(!channel.receive(1, 0, 1));
+    }
+
+    #[test]
+    fn restarted_peer_is_not_taken_for_a_duplicate() {
+        let clock = SimClock::new();
+        let mut receiver = channel(&clock);
+        let mut first = channel(&clock);
+        let sequence = first.register(0, &Packet::RegisterAck);
+        assert!(receiver.receive(1, first.epoch(), sequence));
+
+        // The same drone started again counts from the start
+        let mut second = channel(&clock);
+        assert_ne!(first.epoch(), second.epoch());
+        let sequence = second.register(0, &Packet::RegisterAck);
+        assert_eq!(sequence, 1);
+        assert!(receiver.receive(1, second.epoch(), sequence));
+        assert!(!receiver.receive(1, second.epoch(), sequence));
+    }
+
+    #[test]
+    fn unacknowledged_packet_is_retransmitted_then_given_up() {
+        let clock = SimClock::new();
+        let mut channel = channel(&clock);
+        channel.register(1, &Packet::RegisterAck);
+
+        let mut retransmissions = 0;
+        for _ in 0..10 {
+            clock.advance(Duration::from_millis(500));
+            for timeout in channel.poll() {
+                match timeout {
+                    Timeout::Retransmit { .. } => retransmissions += 1,
+                    Timeout::Failed { to } => {
+                        assert_eq!(to, 1);
+                        assert_eq!(retransmissions, 2);
+                        return;
+                    }
+                }
+            }
+        }
+        panic!("the channel never gave up");
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use mesh_proto::Coordinate;
+
+use crate::clock::Clock;
+use crate::config::DroneConfig;
+
+#[derive(Debug)]
+pub struct Neighbor {
+    pub id: usize,
+    pub position: Coordinate,
+    pub last_seen: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub destination: usize,
+    pub next_hop: usize,
+    pub hop_count: u32,
+    pub destination_sequence: u32,
+    pub valid: bool,
+    // Neighbors that use this drone as next hop towards the destination
+    pub precursors: BTreeSet<usize>,
+    // Valid routes are invalidated at this point, invalid routes are deleted
+    pub expires: Instant,
+}
+
+pub struct RoutingTable {
+    pub neighbors: Vec<Neighbor>,
+    pub routes: BTreeMap<usize, Route>,
+    active_route_timeout: Duration,
+    delete_period: Duration,
+    verbose: bool,
+    clock: Rc<dyn Clock>,
+}
+
+impl RoutingTable {
+    pub fn new(config: &DroneConfig, clock: Rc<dyn Clock>) -> Self {
+        RoutingTable {
+            neighbors: Vec::new(),
+            routes: BTreeMap::new(),
+            active_route_timeout: config.active_route_timeout,
+            delete_period: config.delete_period(),
+            verbose: config.verbose,
+            clock,
+        }
+    }
+
+    pub fn is_neighbor(&self, id: usize) -> bool {
+        self.neighbors.iter().any(|neighbor| neighbor.id == id)
+    }
+
+    pub fn add_neighbor(&mut self, id: usize, position: Coordinate) {
+        self.neighbors.push(Neighbor {
+            id,
+            position,
+            last_seen: self.clock.now(),
+        });
+        self.update_neighbor_route(id);
+    }
+
+    // Only valid routes can be used to forward packets
+    pub fn route_to(&self, destination: usize) -> Option<&Route> {
+        self.routes.get(&destination).filter(|route| route.valid)
+    }
+
+    // Extends the lifetime of a route that is actively used to forward packets
+    pub fn refresh_route(&mut self, destination: usize) {
+        if let Some(route) = self.routes.get_mut(&destination) {
+            if route.valid {
+                route.expires = self.clock.now() + self.active_route_timeout;
+            }
+        }
+    }
+
+    // Replaces the current route if the new one is fresher (higher sequence number)
+    // or equally fresh but shorter, as described in the AODV specification.
+    pub fn update_route(
+        &mut self,
+        destination: usize,
+        next_hop: usize,
+        hop_count: u32,
+        destination_sequence: u32,
+    ) -> bool {
+        let fresher = match self.routes.get(&destination) {
+            None => true,
+            Some(route) => {
+                !route.valid
+                    || destination_sequence > route.destination_sequence
+                    || (destination_sequence == route.destination_sequence
+                        && hop_count < route.hop_count)
+            }
+        };
+
+        if fresher {
+            let precursors = self.take_precursors(destination);
+            self.routes.insert(
+                destination,
+                Route {
+                    destination,
+                    next_hop,
+                    hop_count,
+                    destination_sequence,
+                    valid: true,
+                    precursors,
+                    expires: self.clock.now() + self.active_route_timeout,
+                },
+            );
+        }
+        fresher
+    }
+
+    // A packet received directly from a neighbor always gives a one hop route to it
+    pub fn update_neighbor_route(&mut self, neighbor: usize) {
+        if let Some(known) = self.neighbors.iter_mut().find(|n| n.id == neighbor) {
+            known.last_seen = self.clock.now();
+        }
+
+        let destination_sequence = self
+            .routes
+            .get(&neighbor)
+            .map(|route| route.destination_sequence)
+            .unwrap_or(0);
+
+        let precursors = self.take_precursors(neighbor);
+        self.routes.insert(
+            neighbor,
+            Route {
+                destination: neighbor,
+                next_hop: neighbor,
+                hop_count: 1,
+                destination_sequence,
+                valid: true,
+                precursors,
+                expires: self.clock.now() + self.active_route_timeout,
+            },
+        );
+    }
+
+    pub fn add_precursor(&mut self, destination: usize, precursor: usize) {
+        if let Some(route) = self.routes.get_mut(&destination) {
+            route.precursors.insert(precursor);
+        }
+    }
+
+    fn take_precursors(&mut self, destination: usize) -> BTreeSet<usize> {
+        self.routes
+            .get_mut(&destination)
+            .map(|route| std::mem::take(&mut route.precursors))
+            .unwrap_or_default()
+    }
+
+    pub fn remove_neighbor(&mut self, id: usize) {
+        self.neighbors.retain(|neighbor| neighbor.id != id);
+    }
+
+    // Neighbors that have not been heard from within the timeout
+    pub fn expired_neighbors(&self, timeout: Duration) -> Vec<usize> {
+        let now = self.clock.now();
+        self.neighbors
+            .iter()
+            .filter(|neighbor| now.duration_since(neighbor.last_seen) > timeout)
+            .map(|neighbor| neighbor.id)
+            .collect()
+    }
+
+    // Invalidates valid routes whose lifetime has run out and deletes invalid
+    // routes that have been kept for the delete period.
+    pub fn expire_routes(&mut self) {
+        let now = self.clock.now();
+        let delete_period = self.delete_period;
+        self.routes
+            .retain(|_, route| route.valid || route.expires > now);
+        for route in self.routes.values_mut() {
+            if route.valid && route.expires <= now {
+                if self.verbose {
+                    println!("Route to {} expired", route.destination);
+                }
+                route.valid = false;
+                route.expires = now + delete_period;
+            }
+        }
+    }
+
+    // Marks every valid route using the broken next hop as invalid and bumps its
+    // destination sequence number, returning the routes that were invalidated.
+    pub fn invalidate_routes_through(&mut self, next_hop: usize) -> Vec<Route> {
+        let expires = self.clock.now() + self.delete_period;
+        let mut invalidated = Vec::new();
+        for route in self.routes.values_mut() {
+            if route.valid && route.next_hop == next_hop {
+                route.valid = false;
+                route.destination_sequence += 1;
+                route.expires = expires;
+                invalidated.push(route.clone());
+            }
+        }
+        invalidated
+    }
+
+    // Invalidates the route to the destination if it goes through the given next hop
+    pub fn invalidate_route(
+        &mut self,
+        destination: usize,
+        next_hop: usize,
+        destination_sequence: u32,
+    ) -> Option<Route> {
+        let expires = self.clock.now() + self.delete_period;
+        let route = self.routes.get_mut(&destination)?;
+        if !route.valid || route.next_hop != next_hop {
+            return None;
+        }
+
+        route.valid = false;
+        route.destination_sequence = route.destination_sequence.max(destination_sequence);
+        route.expires = expires;
+        Some(route.clone())
+    }
+}
@@ -0,0 +1,89 @@
+use mesh_proto::Coordinate;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::PI;
+
+use crate::config::DroneConfig;
+
+// The heat of a person against the ground is half as clear from this altitude
+const HALF_CONTRAST_ALTITUDE: f32 = 100.0;
+// Something warm that is not a person never looks more certain than this
+const MAX_FALSE_POSITIVE_CONFIDENCE: f32 = 0.5;
+
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub position: Coordinate,
+    pub confidence: f32,
+}
+
+// A downward thermal camera. The simulator tells it where the people in view are, and it
+// spots each of them with a chance that is best right below the drone and at low altitude.
+// Now and then it also sees a person where there is none.
+pub struct ThermalSensor {
+    altitude: f32,
+    footprint_radius: f32,
+    detection_probability: f32,
+    false_positive_rate: f32,
+    rng: StdRng,
+}
+
+impl ThermalSensor {
+    pub fn new(id: usize, config: &DroneConfig) -> Self {
+        ThermalSensor {
+            altitude: config.altitude,
+            footprint_radius: config.footprint_radius(),
+            detection_probability: config.detection_probability,
+            false_positive_rate: config.false_positive_rate,
+            rng: StdRng::seed_from_u64(config.sensor_seed.wrapping_add(id as u64)),
+        }
+    }
+
+    pub fn footprint_radius(&self) -> f32 {
+        self.footprint_radius
+    }
+
+    // Looks at one camera frame taken at `position` with people at `sources`
+    pub fn scan(&mut self, position: &Coordinate, sources: &[Coordinate]) -> Vec<Detection> {
+        let mut detections = Vec::new();
+        for source in sources {
+            let probability = self.probability(position, source);
+            if self.rng.gen::<f32>() < probability {
+                detections.push(Detection {
+                    position: source.clone(),
+                    confidence: probability,
+                });
+            }
+        }
+
+        if self.rng.gen::<f32>() < self.false_positive_rate {
+            let angle = self.rng.gen_range(0.0..2.0 * PI);
+            // Square root so the points are spread evenly over the footprint
+            let distance = self.footprint_radius * self.rng.gen::<f32>().sqrt();
+            let confidence =
+                self.rng.gen_range(0.0..MAX_FALSE_POSITIVE_CONFIDENCE) * self.contrast();
+            detections.push(Detection {
+                position: Coordinate {
+                    x: position.x + distance * angle.cos(),
+                    y: position.y + distance * angle.sin(),
+                },
+                confidence,
+            });
+        }
+        detections
+    }
+
+    // Falls off towards the edge of the footprint, where the camera looks at an angle
+    fn probability(&self, position: &Coordinate, source: &Coordinate) -> f32 {
+        let dx = source.x - position.x;
+        let dy = source.y - position.y;
+        let offset = (dx * dx + dy * dy).sqrt() / self.footprint_radius;
+        if self.footprint_radius <= 0.0 || offset > 1.0 {
+            return 0.0;
+        }
+        self.detection_probability * self.contrast() * (1.0 - 0.5 * offset * offset)
+    }
+
+    fn contrast(&self) -> f32 {
+        let ratio = self.altitude / HALF_CONTRAST_ALTITUDE;
+        1.0 / (1.0 + ratio * ratio)
+    }
+}
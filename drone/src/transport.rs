@@ -0,0 +1,189 @@
+use mesh_proto::addressing::{drone_address, drone_bind_address, MAX_DRONES};
+use mesh_proto::relay;
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Endpoint {
+    Drone(usize),
+    Simulator,
+}
+
+// How a drone exchanges encoded packets with its neighbors and the simulator
+pub trait Transport {
+    fn send(&self, data: &[u8], to: Endpoint) -> io::Result<()>;
+
+    // Sends to every drone that could be listening, except the sender itself
+    fn broadcast(&self, data: &[u8]) -> io::Result<()>;
+
+    // Waits up to `timeout` for a datagram. Returns None if nothing arrived in time.
+    fn recv(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<Option<usize>>;
+}
+
+pub struct UdpTransport {
+    id: usize,
+    socket: UdpSocket,
+    simulator_address: SocketAddr,
+    // Traffic for other drones goes through the simulator's radio model instead of
+    // straight to them
+    relay_address: Option<SocketAddr>,
+}
+
+impl UdpTransport {
+    pub fn bind(id: usize, simulator_address: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(drone_bind_address(id)?)?;
+        Ok(UdpTransport {
+            id,
+            socket,
+            simulator_address,
+            relay_address: None,
+        })
+    }
+
+    pub fn with_relay(mut self, relay_address: SocketAddr) -> Self {
+        self.relay_address = Some(relay_address);
+        self
+    }
+
+    fn address(&self, to: Endpoint) -> io::Result<SocketAddr> {
+        match to {
+            Endpoint::Drone(id) => drone_address(id),
+            Endpoint::Simulator => Ok(self.simulator_address),
+        }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&self, data: &[u8], to: Endpoint) -> io::Result<()> {
+        if let (Endpoint::Drone(id), Some(relay_address)) = (to, self.relay_address) {
+            self.socket
+                .send_to(&relay::frame(Some(id), data), relay_address)?;
+            return Ok(());
+        }
+        self.socket.send_to(data, self.address(to)?)?;
+        Ok(())
+    }
+
+    // There is no discovery on plain UDP, so every drone port is tried
+    fn broadcast(&self, data: &[u8]) -> io::Result<()> {
+        if let Some(relay_address) = self.relay_address {
+            self.socket
+                .send_to(&relay::frame(None, data), relay_address)?;
+            return Ok(());
+        }
+        for id in 0..MAX_DRONES {
+            if id == self.id {
+                continue;
+            }
+            let address = drone_address(id)?;
+            if let Err(e) = self.socket.send_to(data, address) {
+                println!("Could not send to {}: {}", address, e);
+            }
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<Option<usize>> {
+        // A zero read timeout is rejected by the socket
+        let timeout = timeout.max(Duration::from_millis(1));
+        self.socket.set_read_timeout(Some(timeout))?;
+        match self.socket.recv_from(buffer) {
+            Ok((size, _)) => Ok(Some(size)),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Connects any number of in-process endpoints through channels. Like UDP, packets to an
+// endpoint that does not exist are dropped silently.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    endpoints: Arc<Mutex<BTreeMap<Endpoint, Sender<Vec<u8>>>>>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        MemoryNetwork::default()
+    }
+
+    // Registers the endpoint, replacing any earlier transport for it
+    pub fn connect(&self, endpoint: Endpoint) -> MemoryTransport {
+        let (sender, receiver) = mpsc::channel();
+        self.endpoints.lock().unwrap().insert(endpoint, sender);
+        MemoryTransport {
+            endpoint,
+            network: self.clone(),
+            receiver,
+        }
+    }
+
+    pub fn disconnect(&self, endpoint: Endpoint) {
+        self.endpoints.lock().unwrap().remove(&endpoint);
+    }
+
+    pub fn deliver(&self, data: &[u8], to: Endpoint) {
+        if let Some(sender) = self.endpoints.lock().unwrap().get(&to) {
+            // The receiving transport may already have been dropped
+            let _ = sender.send(data.to_vec());
+        }
+    }
+}
+
+pub struct MemoryTransport {
+    endpoint: Endpoint,
+    network: MemoryNetwork,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl MemoryTransport {
+    pub fn endpoint(&self) -> Endpoint {
+        self.endpoint
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&self, data: &[u8], to: Endpoint) -> io::Result<()> {
+        self.network.deliver(data, to);
+        Ok(())
+    }
+
+    fn broadcast(&self, data: &[u8]) -> io::Result<()> {
+        let drones: Vec<Endpoint> = self
+            .network
+            .endpoints
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|endpoint| {
+                matches!(endpoint, Endpoint::Drone(_)) && **endpoint != self.endpoint
+            })
+            .copied()
+            .collect();
+        for drone in drones {
+            self.network.deliver(data, drone);
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<Option<usize>> {
+        let data = match self.receiver.recv_timeout(timeout) {
+            Ok(data) => data,
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                return Ok(None)
+            }
+        };
+        if data.len() > buffer.len() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("datagram of {} bytes does not fit the buffer", data.len()),
+            ));
+        }
+        buffer[..data.len()].copy_from_slice(&data);
+        Ok(Some(data.len()))
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+use drone::{Drone, DroneConfig, Endpoint, MemoryNetwork, MemoryTransport, SimClock, Transport};
+use mesh_proto::{Coordinate, Packet, RouteEntry, MAX_PACKET_SIZE};
+
+const TICK: Duration = Duration::from_millis(10);
+// Each drone handles at most one packet per step, this is plenty for a few drones
+const ROUNDS_PER_TICK: usize = 20;
+
+// Drones on an in-memory network and simulated time. The test plays the simulator: it
+// answers registrations and keeps every packet the drones send it.
+struct Swarm {
+    network: MemoryNetwork,
+    simulator: MemoryTransport,
+    clock: SimClock,
+    drones: BTreeMap<usize, Drone>,
+    reports: Vec<Packet>,
+}
+
+impl Swarm {
+    fn new(positions: &[(f32, f32)], config: DroneConfig) -> Self {
+        let network = MemoryNetwork::new();
+        let simulator = network.connect(Endpoint::Simulator);
+        let clock = SimClock::new();
+        // Every sent packet is copied to the simulator, so the tests can see the traffic
+        let config = DroneConfig {
+            trace: true,
+            ..config
+        };
+        let drones = positions
+            .iter()
+            .enumerate()
+            .map(|(id, &(x, y))| {
+                let drone = Drone::with_clock(
+                    id,
+                    Coordinate { x, y },
+                    network.connect(Endpoint::Drone(id)),
+                    false,
+                    config.clone(),
+                    Rc::new(clock.clone()),
+                );
+                (id, drone)
+            })
+            .collect();
+        Swarm {
+            network,
+            simulator,
+            clock,
+            drones,
+            reports: Vec::new(),
+        }
+    }
+
+    fn run(&mut self, duration: Duration) {
+        let mut elapsed = Duration::ZERO;
+        while elapsed < duration {
+            self.clock.advance(TICK);
+            elapsed += TICK;
+            for _ in 0..ROUNDS_PER_TICK {
+                for drone in self.drones.values_mut() {
+                    drone.step(Duration::ZERO);
+                }
+                self.receive_reports();
+            }
+        }
+    }
+
+    fn receive_reports(&mut self) {
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        while let Ok(Some(size)) = self.simulator.recv(&mut buffer, Duration::ZERO) {
+            let packet = Packet::decode(&buffer[..size]).expect("drones send valid packets");
+            if let Packet::Register { id, .. } = packet {
+                self.send(Packet::RegisterAck, id);
+            }
+            self.reports.push(packet);
+        }
+    }
+
+    fn send(&self, packet: Packet, to: usize) {
+        self.simulator
+            .send(&packet.encode().unwrap(), Endpoint::Drone(to))
+            .unwrap();
+    }
+
+    // Takes the drone off the network, like a drone that has flown out of range of everyone
+    fn remove(&mut self, id: usize) {
+        self.network.disconnect(Endpoint::Drone(id));
+        self.drones.remove(&id);
+    }
+
+    // Packets sent since the last call, with sender and receiver
+    fn take_traffic(&mut self) -> Vec<(usize, Option<usize>, Packet)> {
+        let reports = std::mem::take(&mut self.reports);
+        reports
+            .into_iter()
+            .filter_map(|report| match report {
+                Packet::Trace {
+                    sender,
+                    receiver,
+                    data,
+                    ..
+                } => Some((sender, receiver, Packet::decode(&data).unwrap())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn routing_table(&mut self, id: usize) -> (Vec<usize>, Vec<RouteEntry>) {
+        self.send(Packet::RoutingTableRequest, id);
+        self.run(TICK);
+        self.reports
+            .iter()
+            .rev()
+            .find_map(|report| match report {
+                Packet::RoutingTableReport {
+                    id: reporter,
+                    neighbors,
+                    routes,
+                } if *reporter == id => Some((neighbors.clone(), routes.clone())),
+                _ => None,
+            })
+            .expect("drone answers routing table requests")
+    }
+
+    fn route(&mut self, from: usize, to: usize) -> Option<RouteEntry> {
+        let (_, routes) = self.routing_table(from);
+        routes.into_iter().find(|route| route.destination == to)
+    }
+}
+
+// Drones 100 apart in a line, each only in range of the ones next to it
+fn chain(length: usize) -> Swarm {
+    let positions: Vec<(f32, f32)> = (0..length).map(|i| (i as f32 * 100.0, 0.0)).collect();
+    let mut swarm = Swarm::new(&positions, DroneConfig::default());
+    swarm.run(Duration::from_millis(100));
+    swarm.take_traffic();
+    swarm
+}
+
+fn send_data(swarm: &mut Swarm, from: usize, to: usize, payload: &str) {
+    swarm.send(
+        Packet::Send {
+            destination: to,
+            payload: payload.to_string(),
+        },
+        from,
+    );
+    swarm.run(Duration::from_millis(100));
+}
+
+#[test]
+fn route_discovery_across_a_chain() {
+    let mut swarm = chain(4);
+    send_data(&mut swarm, 0, 3, "hello");
+
+    let traffic = swarm.take_traffic();
+    let sent = |from: usize, to: usize, name: &str| {
+        traffic.iter().any(|(sender, receiver, packet)| {
+            *sender == from && *receiver == Some(to) && packet.name() == name
+        })
+    };
+    assert!(sent(0, 1, "RREQ"));
+    assert!(sent(1, 2, "RREQ"));
+    // Drone 2 knows drone 3 as a neighbor, so it answers for it
+    assert!(sent(2, 1, "RREP"));
+    assert!(sent(1, 0, "RREP"));
+    assert!(traffic.iter().any(|(sender, receiver, packet)| {
+        *sender == 2
+            && *receiver == Some(3)
+            && matches!(packet, Packet::Data { origin: 0, payload, .. } if payload == "hello")
+    }));
+
+    let route = swarm.route(0, 3).expect("route to the end of the chain");
+    assert_eq!(route.next_hop, 1);
+    assert_eq!(route.hop_count, 3);
+    assert!(route.valid);
+    let reverse = swarm.route(2, 0).expect("reverse route to the origin");
+    assert_eq!(reverse.next_hop, 1);
+    assert_eq!(reverse.hop_count, 2);
+}
+
+#[test]
+fn route_error_after_a_link_break() {
+    let mut swarm = chain(3);
+    send_data(&mut swarm, 0, 2, "hello");
+    assert!(swarm.route(0, 2).is_some_and(|route| route.valid));
+    swarm.take_traffic();
+
+    swarm.remove(2);
+    let config = DroneConfig::default();
+    swarm.run(config.neighbor_timeout() + config.hello_interval);
+
+    let traffic = swarm.take_traffic();
+    assert!(traffic.iter().any(|(sender, receiver, packet)| {
+        *sender == 1
+            && *receiver == Some(0)
+            && matches!(packet, Packet::RouteError { unreachable, .. }
+                if unreachable.iter().any(|(destination, _)| *destination == 2))
+    }));
+    assert!(swarm.route(0, 2).is_some_and(|route| !route.valid));
+}
+
+#[test]
+fn duplicate_flood_is_dropped() {
+    // Three drones in range of each other
+    let mut swarm = Swarm::new(
+        &[(0.0, 0.0), (100.0, 0.0), (50.0, 80.0)],
+        DroneConfig::default(),
+    );
+    swarm.run(Duration::from_millis(100));
+    swarm.take_traffic();
+
+    // Drone 0 floods to both its neighbors, who pass it on to each other
+    let message = Packet::Message {
+        sender: 0,
+        origin: 0,
+        sequence: 1,
+        payload: "flood".to_string(),
+    };
+    swarm.send(message.clone(), 1);
+    swarm.send(message, 2);
+    swarm.run(Duration::from_millis(100));
+
+    let forwarded: Vec<(usize, Option<usize>)> = swarm
+        .take_traffic()
+        .into_iter()
+        .filter(|(_, _, packet)| matches!(packet, Packet::Message { .. }))
+        .map(|(sender, receiver, _)| (sender, receiver))
+        .collect();
+    assert_eq!(forwarded, vec![(1, Some(2)), (2, Some(1))]);
+}
+
+#[test]
+fn reliable_delivery_retransmits_then_gives_up() {
+    let config = DroneConfig {
+        reliable_delivery: true,
+        // Keep the neighbor from timing out, so only the retransmissions can break the link
+        allowed_hello_loss: 1000,
+        ..DroneConfig::default()
+    };
+    let mut swarm = Swarm::new(&[(0.0, 0.0), (100.0, 0.0)], config.clone());
+    // Neighbor routes are made when a known neighbor says hello again
+    swarm.run(config.hello_interval + Duration::from_millis(100));
+    assert!(swarm.route(0, 1).is_some_and(|route| route.valid));
+    swarm.take_traffic();
+
+    swarm.remove(1);
+    send_data(&mut swarm, 0, 1, "anyone there");
+    // The timeout doubles for every retransmission
+    let backoff: Duration = (0..=config.max_retries)
+        .map(|attempt| config.retransmit_timeout * 2u32.pow(attempt))
+        .sum();
+    swarm.run(backoff + Duration::from_millis(100));
+
+    let attempts: Vec<u32> = swarm
+        .take_traffic()
+        .into_iter()
+        .filter_map(|(sender, receiver, packet)| match packet {
+            Packet::Reliable {
+                sequence, packet, ..
+            } if sender == 0 && receiver == Some(1) && matches!(*packet, Packet::Data { .. }) => {
+                Some(sequence)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(attempts.len() as u32, 1 + config.max_retries);
+    assert!(attempts.iter().all(|sequence| *sequence == attempts[0]));
+
+    let (neighbors, _) = swarm.routing_table(0);
+    assert!(!neighbors.contains(&1));
+    assert!(swarm.route(0, 1).is_none_or(|route| !route.valid));
+}
@@ -0,0 +1,72 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+
+// Drone n listens on STANDARD_PORT + n
+pub const STANDARD_PORT: u16 = 8080;
+// HELLO beacons are sent to every port a drone can be bound to, like a radio broadcast
+pub const MAX_DRONES: usize = 64;
+// The simulator receives position updates and reports from the drones here
+pub const SIMULATOR_PORT: u16 = 7878;
+// The simulator sends commands to the drones from this port
+pub const COMMAND_PORT: u16 = 7879;
+// Launched drones send their traffic for other drones here, and the simulator passes it on
+// through its radio model
+pub const RELAY_PORT: u16 = 7877;
+// The HTTP API used by the frontend
+pub const HTTP_PORT: u16 = 8079;
+
+const LOCALHOST: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+// Ids past the last port have none, instead of wrapping around to some other port
+pub fn drone_port(id: usize) -> Option<u16> {
+    u16::try_from(id)
+        .ok()
+        .and_then(|id| STANDARD_PORT.checked_add(id))
+}
+
+pub fn drone_address(id: usize) -> io::Result<SocketAddr> {
+    Ok(SocketAddr::from((LOCALHOST, port(id)?)))
+}
+
+pub fn drone_bind_address(id: usize) -> io::Result<SocketAddr> {
+    Ok(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port(id)?)))
+}
+
+fn port(id: usize) -> io::Result<u16> {
+    drone_port(id).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("drone {} has no port", id),
+        )
+    })
+}
+
+// Maps the source port of a datagram back to the drone that sent it
+pub fn drone_id(address: &SocketAddr) -> Option<usize> {
+    let port = address.port();
+    if port >= STANDARD_PORT && ((port - STANDARD_PORT) as usize) < MAX_DRONES {
+        Some((port - STANDARD_PORT) as usize)
+    } else {
+        None
+    }
+}
+
+pub fn simulator_address() -> SocketAddr {
+    SocketAddr::from((LOCALHOST, SIMULATOR_PORT))
+}
+
+pub fn simulator_bind_address() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::UNSPECIFIED, SIMULATOR_PORT))
+}
+
+pub fn relay_address() -> SocketAddr {
+    SocketAddr::from((LOCALHOST, RELAY_PORT))
+}
+
+pub fn relay_bind_address() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::UNSPECIFIED, RELAY_PORT))
+}
+
+pub fn command_address() -> SocketAddr {
+    SocketAddr::from((LOCALHOST, COMMAND_PORT))
+}
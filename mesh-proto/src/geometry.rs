@@ -0,0 +1,163 @@
+// Plane geometry on polygons given as their corners in order. The last corner connects
+// back to the first.
+use crate::Coordinate;
+
+pub fn distance(a: &Coordinate, b: &Coordinate) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+// Even-odd rule, so it also works for concave polygons
+pub fn contains(polygon: &[Coordinate], point: &Coordinate) -> bool {
+    let mut inside = false;
+    for (a, b) in edges(polygon) {
+        if (a.y > point.y) != (b.y > point.y) {
+            let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+// Shoelace formula
+pub fn area(polygon: &[Coordinate]) -> f32 {
+    signed_area(polygon).abs()
+}
+
+pub fn centroid(polygon: &[Coordinate]) -> Coordinate {
+    let area = signed_area(polygon);
+    if area.abs() < f32::EPSILON {
+        // Degenerate polygon, fall back to the average corner
+        let count = polygon.len().max(1) as f32;
+        return Coordinate {
+            x: polygon.iter().map(|point| point.x).sum::<f32>() / count,
+            y: polygon.iter().map(|point| point.y).sum::<f32>() / count,
+        };
+    }
+
+    let (mut x, mut y) = (0.0, 0.0);
+    for (a, b) in edges(polygon) {
+        let cross = a.x * b.y - b.x * a.y;
+        x += (a.x + b.x) * cross;
+        y += (a.y + b.y) * cross;
+    }
+    Coordinate {
+        x: x / (6.0 * area),
+        y: y / (6.0 * area),
+    }
+}
+
+// Smallest and largest corner of the bounding box
+pub fn bounds(polygon: &[Coordinate]) -> (Coordinate, Coordinate) {
+    let mut min = Coordinate {
+        x: f32::INFINITY,
+        y: f32::INFINITY,
+    };
+    let mut max = Coordinate {
+        x: f32::NEG_INFINITY,
+        y: f32::NEG_INFINITY,
+    };
+    for point in polygon {
+        min.x = min.x.min(point.x);
+        min.y = min.y.min(point.y);
+        max.x = max.x.max(point.x);
+        max.y = max.y.max(point.y);
+    }
+    (min, max)
+}
+
+// Where the horizontal line at y crosses the polygon's edges, sorted left to right.
+// Consecutive pairs are the parts of the line inside the polygon.
+pub fn row_crossings(polygon: &[Coordinate], y: f32) -> Vec<f32> {
+    let mut crossings: Vec<f32> = edges(polygon)
+        .filter(|(a, b)| (a.y > y) != (b.y > y))
+        .map(|(a, b)| a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x))
+        .collect();
+    crossings.sort_by(f32::total_cmp);
+    crossings
+}
+
+// The parts of the line segment from a to b that are inside the polygon, in order from a
+pub fn segment_inside(
+    polygon: &[Coordinate],
+    a: &Coordinate,
+    b: &Coordinate,
+) -> Vec<(Coordinate, Coordinate)> {
+    let along = |t: f32| Coordinate {
+        x: a.x + t * (b.x - a.x),
+        y: a.y + t * (b.y - a.y),
+    };
+    let cross = |u: (f32, f32), v: (f32, f32)| u.0 * v.1 - u.1 * v.0;
+    let direction = (b.x - a.x, b.y - a.y);
+
+    // Where the segment crosses the edges, as fractions of the way from a to b
+    let mut cuts = vec![0.0, 1.0];
+    for (p, q) in edges(polygon) {
+        let edge = (q.x - p.x, q.y - p.y);
+        let denominator = cross(direction, edge);
+        if denominator == 0.0 {
+            continue;
+        }
+        let offset = (p.x - a.x, p.y - a.y);
+        let t = cross(offset, edge) / denominator;
+        let u = cross(offset, direction) / denominator;
+        if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+            cuts.push(t);
+        }
+    }
+    cuts.sort_by(f32::total_cmp);
+    cuts.dedup();
+
+    let mut parts: Vec<(f32, f32)> = Vec::new();
+    for pair in cuts.windows(2) {
+        if !contains(polygon, &along((pair[0] + pair[1]) / 2.0)) {
+            continue;
+        }
+        match parts.last_mut() {
+            // Touching a corner from inside does not end the part
+            Some(last) if last.1 == pair[0] => last.1 = pair[1],
+            _ => parts.push((pair[0], pair[1])),
+        }
+    }
+    parts
+        .into_iter()
+        .map(|(start, end)| (along(start), along(end)))
+        .collect()
+}
+
+// The part of the polygon on the side of a line where normal · point <= offset
+// (Sutherland-Hodgman). A concave polygon cut in several pieces comes back as one polygon
+// with zero-width seams between the pieces, which does not change its area.
+pub fn clip(polygon: &[Coordinate], normal: &Coordinate, offset: f32) -> Vec<Coordinate> {
+    let side = |point: &Coordinate| normal.x * point.x + normal.y * point.y - offset;
+    let mut clipped = Vec::new();
+    for (a, b) in edges(polygon) {
+        let (side_a, side_b) = (side(a), side(b));
+        if side_a <= 0.0 {
+            clipped.push(a.clone());
+        }
+        if (side_a <= 0.0) != (side_b <= 0.0) {
+            let t = side_a / (side_a - side_b);
+            clipped.push(Coordinate {
+                x: a.x + t * (b.x - a.x),
+                y: a.y + t * (b.y - a.y),
+            });
+        }
+    }
+    clipped
+}
+
+fn signed_area(polygon: &[Coordinate]) -> f32 {
+    edges(polygon)
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f32>()
+        / 2.0
+}
+
+fn edges(polygon: &[Coordinate]) -> impl Iterator<Item = (&Coordinate, &Coordinate)> {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .take(polygon.len())
+}
@@ -0,0 +1,75 @@
+// Types shared by the simulator and the drones: the wire protocol, the ports
+// everything listens on and the data both sides pass around.
+pub mod addressing;
+pub mod geometry;
+pub mod packet;
+pub mod relay;
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub use packet::{DecodeError, EncodeError, Packet, MAX_PACKET_SIZE, PROTOCOL_VERSION};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Coordinate {
+    pub x: f32,
+    pub y: f32,
+}
+
+// Position of a drone as reported to the simulator and shown in the frontend
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DroneData {
+    pub id: usize,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl DroneData {
+    pub fn new(id: usize, x: f32, y: f32) -> DroneData {
+        DroneData { id, x, y }
+    }
+}
+
+// One entry of a drone's routing table as reported to the simulator
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RouteEntry {
+    pub destination: usize,
+    pub next_hop: usize,
+    pub hop_count: u32,
+    pub destination_sequence: u32,
+    pub valid: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    // Back and forth in parallel rows
+    Lawnmower,
+    // Outwards from the middle in growing squares
+    ExpandingSquare,
+    // Out and back through the middle in wedges
+    Sector,
+}
+
+// An area for a drone to search and how to fly over it. Spacing is the distance between
+// passes, about the width of what the drone can see.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Mission {
+    pub area: Vec<Coordinate>,
+    pub pattern: Pattern,
+    pub spacing: f32,
+    // Parts of the area with nothing to search, like lakes or buildings. No waypoints are
+    // put there, but the drones may fly over them between two parts of the path.
+    #[serde(default)]
+    pub obstacles: Vec<Vec<Coordinate>>,
+}
+
+// What a drone tells the base station when it has found someone
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SearchReport {
+    pub finder: usize,
+    pub position: Coordinate,
+    // How long the finder had been flying
+    pub time: Duration,
+    pub confidence: f32,
+}
@@ -0,0 +1,616 @@
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::{Coordinate, DroneData, Mission, RouteEntry, SearchReport};
+
+const MAGIC: [u8; 2] = *b"SK";
+pub const PROTOCOL_VERSION: u8 = 1;
+const HEADER_LENGTH: usize = MAGIC.len() + 1;
+pub const MAX_PACKET_SIZE: usize = 1024;
+// Room for the rest of a Trace packet around the copy of the traced packet
+const TRACE_OVERHEAD: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Packet {
+    // Simulator to drone
+    Move {
+        target: Coordinate,
+    },
+    RequestPosition,
+    AddNeighbor {
+        id: usize,
+        position: Coordinate,
+    },
+    Send {
+        destination: usize,
+        payload: String,
+    },
+
+    // Drone to simulator
+    Position(DroneData),
+    Finished {
+        id: usize,
+    },
+
+    // Drone to drone
+    Hello {
+        id: usize,
+        position: Coordinate,
+    },
+    Update {
+        id: usize,
+        position: Coordinate,
+    },
+    // Legacy, drones hold formation instead of asking neighbors to move. Kept so the
+    // variants after it keep their numbers on the wire.
+    MoveRequest {
+        sender: usize,
+        target: Coordinate,
+    },
+    Message {
+        sender: usize,
+        origin: usize,
+        sequence: u32,
+        payload: String,
+    },
+    RouteRequest {
+        sender: usize,
+        broadcast_id: u32,
+        origin: usize,
+        origin_sequence: u32,
+        destination: usize,
+        destination_sequence: u32,
+        hop_count: u32,
+    },
+    RouteReply {
+        sender: usize,
+        origin: usize,
+        destination: usize,
+        destination_sequence: u32,
+        hop_count: u32,
+    },
+    RouteError {
+        sender: usize,
+        // Unreachable destinations and their destination sequence numbers
+        unreachable: Vec<(usize, u32)>,
+    },
+    Data {
+        sender: usize,
+        origin: usize,
+        destination: usize,
+        payload: String,
+    },
+    Reliable {
+        sender: usize,
+        // Tells the sender's restarts apart, its sequence numbers start over each time
+        epoch: u32,
+        sequence: u32,
+        packet: Box<Packet>,
+    },
+    Ack {
+        sender: usize,
+        sequence: u32,
+    },
+
+    // Startup handshake, added after the variants above to keep their encoding
+    Register {
+        id: usize,
+        position: Coordinate,
+    },
+    RegisterAck,
+
+    // Lifecycle and inspection requests from the REST API
+    Shutdown,
+    RoutingTableRequest,
+    RoutingTableReport {
+        id: usize,
+        neighbors: Vec<usize>,
+        routes: Vec<RouteEntry>,
+    },
+
+    // Drone to simulator, for the live view
+    Neighbors {
+        id: usize,
+        neighbors: Vec<usize>,
+    },
+    PacketSent {
+        sender: usize,
+        // None for a broadcast
+        receiver: Option<usize>,
+        kind: String,
+    },
+    // Copy of a packet a drone sent, for packet capture. A packet close to the size limit
+    // does not fit in a trace, so the copy may be cut short like pcap's snapshot length.
+    Trace {
+        sender: usize,
+        receiver: Option<usize>,
+        // Size of the packet as it was sent
+        length: usize,
+        data: Vec<u8>,
+    },
+
+    // Simulator to drone, searching an area instead of flying to a point
+    Mission(Mission),
+    // Moves one step along the mission's coverage path
+    MissionStep,
+    CancelMission,
+    // Drone to simulator, it has flown the whole coverage path
+    MissionComplete {
+        id: usize,
+    },
+
+    // Simulator to drone, the people the thermal camera has in view after the drone moved
+    ThermalFrame {
+        sources: Vec<Coordinate>,
+    },
+    // Drone to simulator, the sensor thinks it has seen a person
+    Detection {
+        id: usize,
+        position: Coordinate,
+        confidence: f32,
+    },
+
+    // Drone to drone, routed like Data from the drone that found someone to the base
+    // station, and the base station's acknowledgement routed back
+    SearchReport {
+        sender: usize,
+        destination: usize,
+        report: SearchReport,
+    },
+    SearchAck {
+        sender: usize,
+        origin: usize,
+        destination: usize,
+    },
+    // Base station to simulator, a search report has arrived
+    ReportReceived(SearchReport),
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    TooShort(usize),
+    BadMagic,
+    UnsupportedVersion(u8),
+    InvalidBody(bincode::Error),
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::TooShort(length) => write!(f, "packet too short ({} bytes)", length),
+            DecodeError::BadMagic => write!(f, "packet does not start with the protocol magic"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported protocol version {}", version)
+            }
+            DecodeError::InvalidBody(e) => write!(f, "invalid packet body: {}", e),
+            DecodeError::TrailingBytes(length) => {
+                write!(f, "{} bytes left over after the packet", length)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+#[derive(Debug)]
+pub enum EncodeError {
+    // Encoded size of the packet, which would be cut short by the receive buffers
+    TooLarge(usize),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::TooLarge(length) => write!(
+                f,
+                "packet too large ({} bytes, at most {} fit)",
+                length, MAX_PACKET_SIZE
+            ),
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+// So sending over a socket can fail with either
+impl From<EncodeError> for io::Error {
+    fn from(e: EncodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+// Variable length integers keep the packets small
+fn body_options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+impl Packet {
+    // Header is the magic bytes followed by the protocol version, then the bincode body
+    // Fails if the packet would not fit in a receive buffer of MAX_PACKET_SIZE
+    pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(PROTOCOL_VERSION);
+        body_options()
+            .serialize_into(&mut bytes, self)
+            .expect("packets always serialize");
+        if bytes.len() > MAX_PACKET_SIZE {
+            return Err(EncodeError::TooLarge(bytes.len()));
+        }
+        Ok(bytes)
+    }
+
+    // A trace of a sent packet, with as much of it as fits
+    pub fn trace(sender: usize, receiver: Option<usize>, data: &[u8]) -> Packet {
+        Packet::Trace {
+            sender,
+            receiver,
+            length: data.len(),
+            data: data[..data.len().min(MAX_PACKET_SIZE - TRACE_OVERHEAD)].to_vec(),
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Packet, DecodeError> {
+        if bytes.len() < HEADER_LENGTH {
+            return Err(DecodeError::TooShort(bytes.len()));
+        }
+        if bytes[..MAGIC.len()] != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = bytes[MAGIC.len()];
+        if version != PROTOCOL_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        // The limit stops a corrupt length prefix from allocating more than a datagram can
+        // hold. bincode only applies it when reading through a reader, not from a slice.
+        let mut body = &bytes[HEADER_LENGTH..];
+        let packet = body_options()
+            .with_limit(MAX_PACKET_SIZE as u64)
+            .deserialize_from(&mut body)
+            .map_err(DecodeError::InvalidBody)?;
+        if !body.is_empty() {
+            return Err(DecodeError::TrailingBytes(body.len()));
+        }
+        Ok(packet)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Packet::Move { .. } => "MOVE",
+            Packet::RequestPosition => "POSITION",
+            Packet::AddNeighbor { .. } => "ADD_NEIGHBOR",
+            Packet::Send { .. } => "SEND",
+            Packet::Position(_) => "POSITION_REPORT",
+            Packet::Finished { .. } => "FINISHED",
+            Packet::Hello { .. } => "HELLO",
+            Packet::Update { .. } => "UPDATE",
+            Packet::MoveRequest { .. } => "MOVE_REQUEST",
+            Packet::Message { .. } => "MESSAGE",
+            Packet::RouteRequest { .. } => "RREQ",
+            Packet::RouteReply { .. } => "RREP",
+            Packet::RouteError { .. } => "RERR",
+            Packet::Data { .. } => "DATA",
+            Packet::Reliable { .. } => "RELIABLE",
+            Packet::Ack { .. } => "ACK",
+            Packet::Register { .. } => "REGISTER",
+            Packet::RegisterAck => "REGISTER_ACK",
+            Packet::Shutdown => "SHUTDOWN",
+            Packet::RoutingTableRequest => "ROUTING_TABLE_REQUEST",
+            Packet::RoutingTableReport { .. } => "ROUTING_TABLE_REPORT",
+            Packet::Neighbors { .. } => "NEIGHBORS",
+            Packet::PacketSent { .. } => "PACKET_SENT",
+            Packet::Trace { .. } => "TRACE",
+            Packet::Mission(_) => "MISSION",
+            Packet::MissionStep => "MISSION_STEP",
+            Packet::CancelMission => "CANCEL_MISSION",
+            Packet::MissionComplete { .. } => "MISSION_COMPLETE",
+            Packet::ThermalFrame { .. } => "THERMAL_FRAME",
+            Packet::Detection { .. } => "DETECTION",
+            Packet::SearchReport { .. } => "SEARCH_REPORT",
+            Packet::SearchAck { .. } => "SEARCH_ACK",
+            Packet::ReportReceived(_) => "REPORT_RECEIVED",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pattern;
+    use std::time::Duration;
+
+    fn coordinate() -> Coordinate {
+        Coordinate { x: 1.5, y: -2.0 }
+    }
+
+    fn report() -> SearchReport {
+        SearchReport {
+            finder: 3,
+            position: coordinate(),
+            time: Duration::from_millis(1500),
+            confidence: 0.75,
+        }
+    }
+
+    // One of every variant
+    fn samples() -> Vec<Packet> {
+        vec![
+            Packet::Move {
+                target: coordinate(),
+            },
+            Packet::RequestPosition,
+            Packet::AddNeighbor {
+                id: 1,
+                position: coordinate(),
+            },
+            Packet::Send {
+                destination: 2,
+                payload: "hello".to_string(),
+            },
+            Packet::Position(DroneData::new(1, 2.0, 3.0)),
+            Packet::Finished { id: 4 },
+            Packet::Hello {
+                id: 1,
+                position: coordinate(),
+            },
+            Packet::Update {
+                id: 1,
+                position: coordinate(),
+            },
+            Packet::MoveRequest {
+                sender: 1,
+                target: coordinate(),
+            },
+            Packet::Message {
+                sender: 1,
+                origin: 2,
+                sequence: 3,
+                payload: "flood".to_string(),
+            },
+            Packet::RouteRequest {
+                sender: 1,
+                broadcast_id: 2,
+                origin: 3,
+                origin_sequence: 4,
+                destination: 5,
+                destination_sequence: 6,
+                hop_count: 7,
+            },
+            Packet::RouteReply {
+                sender: 1,
+                origin: 2,
+                destination: 3,
+                destination_sequence: 4,
+                hop_count: 5,
+            },
+            Packet::RouteError {
+                sender: 1,
+                unreachable: vec![(2, 3), (4, 5)],
+            },
+            Packet::Data {
+                sender: 1,
+                origin: 2,
+                destination: 3,
+                payload: "data".to_string(),
+            },
+            Packet::Reliable {
+                sender: 1,
+                epoch: 7,
+                sequence: 2,
+                packet: Box::new(Packet::Ack {
+                    sender: 3,
+                    sequence: 4,
+                }),
+            },
+            Packet::Ack {
+                sender: 1,
+                sequence: 2,
+            },
+            Packet::Register {
+                id: 1,
+                position: coordinate(),
+            },
+            Packet::RegisterAck,
+            Packet::Shutdown,
+            Packet::RoutingTableRequest,
+            Packet::RoutingTableReport {
+                id: 1,
+                neighbors: vec![2, 3],
+                routes: vec![RouteEntry {
+                    destination: 4,
+                    next_hop: 2,
+                    hop_count: 2,
+                    destination_sequence: 7,
+                    valid: true,
+                }],
+            },
+            Packet::Neighbors {
+                id: 1,
+                neighbors: vec![2],
+            },
+            Packet::PacketSent {
+                sender: 1,
+                receiver: None,
+                kind: "HELLO".to_string(),
+            },
+            Packet::Trace {
+                sender: 1,
+                receiver: Some(2),
+                length: 3,
+                data: vec![0, 1, 255],
+            },
+            Packet::Mission(Mission {
+                area: vec![
+                    Coordinate { x: 0.0, y: 0.0 },
+                    Coordinate { x: 10.0, y: 0.0 },
+                    Coordinate { x: 0.0, y: 10.0 },
+                ],
+                pattern: Pattern::Sector,
+                spacing: 2.5,
+                obstacles: vec![vec![
+                    Coordinate { x: 1.0, y: 1.0 },
+                    Coordinate { x: 2.0, y: 1.0 },
+                    Coordinate { x: 1.0, y: 2.0 },
+                ]],
+            }),
+            Packet::MissionStep,
+            Packet::CancelMission,
+            Packet::MissionComplete { id: 1 },
+            Packet::ThermalFrame {
+                sources: vec![coordinate()],
+            },
+            Packet::Detection {
+                id: 1,
+                position: coordinate(),
+                confidence: 0.5,
+            },
+            Packet::SearchReport {
+                sender: 1,
+                destination: 63,
+                report: report(),
+            },
+            Packet::SearchAck {
+                sender: 63,
+                origin: 63,
+                destination: 3,
+            },
+            Packet::ReportReceived(report()),
+        ]
+    }
+
+    fn header() -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(PROTOCOL_VERSION);
+        bytes
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        let samples = samples();
+        let names: std::collections::BTreeSet<&str> = samples.iter().map(Packet::name).collect();
+        assert_eq!(names.len(), samples.len(), "one sample per variant");
+        for packet in samples {
+            let bytes = packet.encode().unwrap();
+            assert_eq!(Packet::decode(&bytes).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn too_short() {
+        assert!(matches!(Packet::decode(&[]), Err(DecodeError::TooShort(0))));
+        assert!(matches!(
+            Packet::decode(b"SK"),
+            Err(DecodeError::TooShort(2))
+        ));
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = Packet::RegisterAck.encode().unwrap();
+        bytes[0] = b'X';
+        assert!(matches!(Packet::decode(&bytes), Err(DecodeError::BadMagic)));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut bytes = Packet::RegisterAck.encode().unwrap();
+        bytes[MAGIC.len()] = PROTOCOL_VERSION + 1;
+        assert!(matches!(
+            Packet::decode(&bytes),
+            Err(DecodeError::UnsupportedVersion(version)) if version == PROTOCOL_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn garbage_body() {
+        let mut bytes = header();
+        bytes.extend_from_slice(&[0xff, 0xff, 0xff]);
+        assert!(matches!(
+            Packet::decode(&bytes),
+            Err(DecodeError::InvalidBody(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_body() {
+        let bytes = Packet::Send {
+            destination: 2,
+            payload: "hello".to_string(),
+        }
+        .encode()
+        .unwrap();
+        assert!(matches!(
+            Packet::decode(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::InvalidBody(_))
+        ));
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut bytes = Packet::RegisterAck.encode().unwrap();
+        bytes.extend_from_slice(&[0, 0]);
+        assert!(matches!(
+            Packet::decode(&bytes),
+            Err(DecodeError::TrailingBytes(2))
+        ));
+    }
+
+    #[test]
+    fn oversized_length_prefix_hits_the_limit() {
+        // Send (variant 3) to drone 0, with a payload claiming to be u64::MAX bytes long
+        let mut bytes = header();
+        bytes.extend_from_slice(&[3, 0, 253]);
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        match Packet::decode(&bytes) {
+            Err(DecodeError::InvalidBody(e)) => {
+                assert!(matches!(*e, bincode::ErrorKind::SizeLimit), "{}", e)
+            }
+            other => panic!("expected the size limit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_packet_is_not_encoded() {
+        let payload = |length| Packet::Send {
+            destination: 2,
+            payload: "x".repeat(length),
+        };
+        // Header, variant, destination and a three byte length prefix
+        let fits = MAX_PACKET_SIZE - 3 - 1 - 1 - 3;
+        assert_eq!(payload(fits).encode().unwrap().len(), MAX_PACKET_SIZE);
+        assert!(matches!(
+            payload(fits + 1).encode(),
+            Err(EncodeError::TooLarge(length)) if length == MAX_PACKET_SIZE + 1
+        ));
+    }
+
+    #[test]
+    fn trace_of_the_largest_packet_fits() {
+        let data = vec![7; MAX_PACKET_SIZE];
+        let trace = Packet::trace(usize::MAX, Some(usize::MAX), &data);
+        assert!(trace.encode().is_ok());
+        match trace {
+            Packet::Trace { length, data, .. } => {
+                assert_eq!(length, MAX_PACKET_SIZE);
+                assert_eq!(data.len(), MAX_PACKET_SIZE - TRACE_OVERHEAD);
+            }
+            other => panic!("expected a trace, got {:?}", other),
+        }
+
+        let small = Packet::trace(1, None, &[1, 2, 3]);
+        assert_eq!(
+            small,
+            Packet::Trace {
+                sender: 1,
+                receiver: None,
+                length: 3,
+                data: vec![1, 2, 3],
+            }
+        );
+    }
+}
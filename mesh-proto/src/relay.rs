@@ -0,0 +1,47 @@
+// Framing for drone traffic that goes through the simulator's relay. Each datagram is an
+// encoded packet behind the id of the drone it is for.
+
+// Size of the destination in front of the packet
+pub const HEADER_SIZE: usize = 2;
+
+const BROADCAST: u16 = u16::MAX;
+
+// Puts the destination in front of the data. None is a broadcast to every drone in range.
+pub fn frame(to: Option<usize>, data: &[u8]) -> Vec<u8> {
+    let to = to
+        .and_then(|id| u16::try_from(id).ok())
+        .unwrap_or(BROADCAST);
+    let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+    frame.extend_from_slice(&to.to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+// Splits a frame into its destination and data, or None if it is too short to have one
+pub fn unframe(frame: &[u8]) -> Option<(Option<usize>, &[u8])> {
+    let (header, data) = frame.split_at_checked(HEADER_SIZE)?;
+    let to = u16::from_be_bytes([header[0], header[1]]);
+    let to = if to == BROADCAST {
+        None
+    } else {
+        Some(to as usize)
+    };
+    Some((to, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_round_trips() {
+        let data = [1, 2, 3];
+        assert_eq!(unframe(&frame(Some(5), &data)), Some((Some(5), &data[..])));
+        assert_eq!(unframe(&frame(None, &data)), Some((None, &data[..])));
+    }
+
+    #[test]
+    fn short_frame_is_rejected() {
+        assert_eq!(unframe(&[7]), None);
+    }
+}